@@ -6,11 +6,19 @@ let first = 1;
     const chartCanvas_gas = document.getElementById("gas-chart");
     const chartCanvas_txsvalue = document.getElementById("txs-value-chart");
     const chartCanvas_txsvaluemin = document.getElementById("txs-value-min-chart");
+    const chartCanvas_withdrawals = document.getElementById("withdrawals-chart");
+    const chartCanvas_blobgas = document.getElementById("blob-gas-chart");
+    const chartCanvas_blobfee = document.getElementById("blob-fee-chart");
+    const chartCanvas_burn = document.getElementById("burn-chart");
     let chart;
     let chart2;
     let chart3;
     let chart4;
     let chart5;
+    let chart6;
+    let chart7;
+    let chart8;
+    let chart9;
 
  
 
@@ -20,21 +28,58 @@ let first = 1;
         return data;
     }
 
-    async function fetchHistoricData() {
-        return await fetchData("http://127.0.0.1:8080/historic-data");
+    // Latest stored blocks shown by the charts
+    const CHART_BLOCKS = 500;
+
+    async function fetchChartRange() {
+        const stored = await fetchData("http://127.0.0.1:8080/stats/range");
+        if (stored.to === null) {
+            return null;
+        }
+        return { from: Math.max(stored.from, stored.to - CHART_BLOCKS + 1), to: stored.to };
+    }
+
+    async function fetchBurnData(range) {
+        if (range === null) {
+            return { series: [] };
+        }
+        return await fetchData(`http://127.0.0.1:8080/stats/burn?from=${range.from}&to=${range.to}&bucket=block&units=eth`);
+    }
+
+    async function fetchSeriesData(range, bucket) {
+        if (range === null) {
+            return [];
+        }
+        return await fetchData(`http://127.0.0.1:8080/stats/series?from=${range.from}&to=${range.to}&bucket=${bucket}&units=eth`);
     }
 
+    let latestBlocks = [];
+
     async function displayData() {
-        const transactions = await fetchData("http://127.0.0.1:8080/transactions");
-        const blocks = await fetchData("http://127.0.0.1:8080/blocks");
+        const transactions = (await fetchData("http://127.0.0.1:8080/transactions?count=10")).transactions;
+        latestBlocks = (await fetchData("http://127.0.0.1:8080/blocks?count=10")).blocks;
 
         document.getElementById("transactionData").innerText = JSON.stringify(transactions, null, 2);
-        document.getElementById("blockData").innerText = JSON.stringify(blocks, null, 2);
+        document.getElementById("blockData").innerText = JSON.stringify(latestBlocks, null, 2);
     }
 
-    function createTransactionChart(historicData) {
-        const blockNumbers = historicData.map(block => block.number);
-        const transactionCounts = historicData.map(block => block.transaction_number);
+    // New heads pushed by the server, instead of polling /blocks and /transactions
+    function subscribeHeads() {
+        const heads = new EventSource("http://127.0.0.1:8080/stream/heads?transactions=true");
+        heads.addEventListener("head", event => {
+            const block = JSON.parse(event.data).block;
+            const transactions = block.transactions.slice(0, 10);
+            delete block.transactions;
+            latestBlocks = [block, ...latestBlocks].slice(0, 10);
+
+            document.getElementById("transactionData").innerText = JSON.stringify(transactions, null, 2);
+            document.getElementById("blockData").innerText = JSON.stringify(latestBlocks, null, 2);
+        });
+    }
+
+    function createTransactionChart(blockSeries) {
+        const blockNumbers = blockSeries.map(bucket => bucket.bucket);
+        const transactionCounts = blockSeries.map(bucket => bucket.transaction_count);
 
         chart = new Chart(chartCanvas_transaction, {
             type: 'line',
@@ -58,21 +103,17 @@ let first = 1;
         });
     }
 
-    function createAvgGasChart(historicData) {
-        const blockNumbers = historicData.map(block => block.number);
-        const gasPrices = historicData.map(block => {
-            // Calculate the average gas price of all transactions within the block
-            const totalGasPrice = block.transactions.reduce((sum, transaction) => sum + Number(transaction.gas_price), 0);
-            const avgGasPrice = block.transactions.length > 0 ? totalGasPrice / block.transactions.length : 0;
-            return avgGasPrice;
-        });
+    function createAvgGasChart(blockSeries) {
+        const blockNumbers = blockSeries.map(bucket => bucket.bucket);
+        // Average gas price computed by the server, in gwei
+        const gasPrices = blockSeries.map(bucket => Number(bucket.avg_gas_price_gwei));
     
         chart2 = new Chart(chartavgCanvas_gas, {
             type: 'line',
             data: {
                 labels: blockNumbers,
                 datasets: [{
-                    label: 'Average Gas Price per Block',
+                    label: 'Average Gas Price per Block (gwei)',
                     data: gasPrices,
                     backgroundColor: 'rgba(75, 192, 192, 0.2)',
                     borderColor: 'rgba(75, 192, 192, 1)',
@@ -89,20 +130,17 @@ let first = 1;
         });
     }
 
-    function createGasChart(historicData) {
-        const blockNumbers = historicData.map(block => block.number);
-        const totalGasPrices = historicData.map(block => {
-            // Calculate the total gas price of all transactions within the block
-            const totalGasPrice = block.transactions.reduce((sum, transaction) => sum + Number(transaction.gas_price), 0);
-            return totalGasPrice;
-        });
+    function createGasChart(blockSeries) {
+        const blockNumbers = blockSeries.map(bucket => bucket.bucket);
+        // Total fees paid computed by the server, in ETH
+        const totalGasPrices = blockSeries.map(bucket => Number(bucket.total_fees_eth));
     
         chart3 = new Chart(chartCanvas_gas, {
             type: 'line',
             data: {
                 labels: blockNumbers,
                 datasets: [{
-                    label: 'Total Gas Price per Block',
+                    label: 'Total Fees per Block (ETH)',
                     data: totalGasPrices,
                     backgroundColor: 'rgba(75, 192, 192, 0.2)',
                     borderColor: 'rgba(75, 192, 192, 1)',
@@ -119,20 +157,17 @@ let first = 1;
         });
     }
     
-    function createTxsValueChart(historicData) {
-        const blockNumbers = historicData.map(block => block.number);
-        const totalValuePrices = historicData.map(block => {
-            // Calculate the total value of all transactions within the block
-            const totalGasPrice = block.transactions.reduce((sum, transaction) => sum + Number(transaction.value), 0);
-            return totalGasPrice;
-        });
+    function createTxsValueChart(blockSeries) {
+        const blockNumbers = blockSeries.map(bucket => bucket.bucket);
+        // Total value computed by the server, in ETH
+        const totalValuePrices = blockSeries.map(bucket => Number(bucket.total_value_eth));
     
         chart4 = new Chart(chartCanvas_txsvalue, {
             type: 'line',
             data: {
                 labels: blockNumbers,
                 datasets: [{
-                    label: 'Total Value per Block',
+                    label: 'Total Value per Block (ETH)',
                     data: totalValuePrices,
                     backgroundColor: 'rgba(75, 192, 192, 0.2)',
                     borderColor: 'rgba(75, 192, 192, 1)',
@@ -151,35 +186,20 @@ let first = 1;
     
 
     
-    function createTransactionValueChart(historicData) {
-        const minutelyTransactionSums = {};
-    
-        for (const block of historicData) {
-            for (const transaction of block.transactions) {
-                // Convert the hexadecimal timestamp to a Date object
-                const timestamp = parseInt(block.timestamp, 16) * 1000;
-                const date = new Date(timestamp);
-    
-                // Create a string representation of the date with minute precision
-                const dateString = `${date.getFullYear()}-${date.getMonth() + 1}-${date.getDate()} ${date.getHours()}:${date.getMinutes()}`;
-    
-                // Calculate the sum of transaction values per minute
-                if (minutelyTransactionSums[dateString] === undefined) {
-                    minutelyTransactionSums[dateString] = 0;
-                }
-                minutelyTransactionSums[dateString] += Number(transaction.value);
-            }
-        }
-    
-        const chartLabels = Object.keys(minutelyTransactionSums);
-        const chartData = chartLabels.map(dateString => minutelyTransactionSums[dateString]);
+    function createTransactionValueChart(minuteSeries) {
+        const chartLabels = minuteSeries.map(bucket => {
+            const date = new Date(bucket.bucket * 1000);
+            return `${date.getFullYear()}-${date.getMonth() + 1}-${date.getDate()} ${date.getHours()}:${date.getMinutes()}`;
+        });
+        // Total value per minute computed by the server, in ETH
+        const chartData = minuteSeries.map(bucket => Number(bucket.total_value_eth));
     
         chart5 = new Chart(chartCanvas_txsvaluemin, {
             type: 'line',
             data: {
                 labels: chartLabels,
                 datasets: [{
-                    label: 'Transaction Value per Minute',
+                    label: 'Transaction Value per Minute (ETH)',
                     data: chartData,
                     backgroundColor: 'rgba(75, 192, 192, 0.2)',
                     borderColor: 'rgba(75, 192, 192, 1)',
@@ -196,6 +216,130 @@ let first = 1;
         });
     }
     
+    function createWithdrawalsChart(blockSeries) {
+        const blockNumbers = blockSeries.map(bucket => bucket.bucket);
+        const withdrawnEth = blockSeries.map(bucket => Number(bucket.withdrawn_eth));
+
+        chart6 = new Chart(chartCanvas_withdrawals, {
+            type: 'line',
+            data: {
+                labels: blockNumbers,
+                datasets: [{
+                    label: 'Withdrawn ETH per Block',
+                    data: withdrawnEth,
+                    backgroundColor: 'rgba(75, 192, 192, 0.2)',
+                    borderColor: 'rgba(75, 192, 192, 1)',
+                    borderWidth: 1
+                }]
+            },
+            options: {
+                scales: {
+                    y: {
+                        beginAtZero: true
+                    }
+                }
+            }
+        });
+    }
+
+    function createBlobGasChart(blockSeries) {
+        const blockNumbers = blockSeries.map(bucket => bucket.bucket);
+        const blobGasUsed = blockSeries.map(bucket => Number(bucket.blob_gas_used));
+
+        chart7 = new Chart(chartCanvas_blobgas, {
+            type: 'line',
+            data: {
+                labels: blockNumbers,
+                datasets: [{
+                    label: 'Blob Gas Used per Block',
+                    data: blobGasUsed,
+                    backgroundColor: 'rgba(75, 192, 192, 0.2)',
+                    borderColor: 'rgba(75, 192, 192, 1)',
+                    borderWidth: 1
+                }]
+            },
+            options: {
+                scales: {
+                    y: {
+                        beginAtZero: true
+                    }
+                }
+            }
+        });
+    }
+
+    function createBlobFeeChart(blockSeries) {
+        const blockNumbers = blockSeries.map(bucket => bucket.bucket);
+        // Blob base fee in gwei
+        const blobBaseFees = blockSeries.map(bucket => Number(bucket.avg_blob_base_fee_gwei));
+
+        chart8 = new Chart(chartCanvas_blobfee, {
+            type: 'line',
+            data: {
+                labels: blockNumbers,
+                datasets: [{
+                    label: 'Blob Base Fee per Block (gwei)',
+                    data: blobBaseFees,
+                    backgroundColor: 'rgba(75, 192, 192, 0.2)',
+                    borderColor: 'rgba(75, 192, 192, 1)',
+                    borderWidth: 1
+                }]
+            },
+            options: {
+                scales: {
+                    y: {
+                        beginAtZero: true
+                    }
+                }
+            }
+        });
+    }
+
+    function createBurnChart(burnData) {
+        const blockNumbers = burnData.series.map(bucket => bucket.bucket);
+        // Burnt fees in ETH
+        const burntEth = burnData.series.map(bucket => Number(bucket.burnt_fees_eth));
+
+        chart9 = new Chart(chartCanvas_burn, {
+            type: 'line',
+            data: {
+                labels: blockNumbers,
+                datasets: [{
+                    label: 'ETH Burned per Block',
+                    data: burntEth,
+                    backgroundColor: 'rgba(75, 192, 192, 0.2)',
+                    borderColor: 'rgba(75, 192, 192, 1)',
+                    borderWidth: 1
+                }]
+            },
+            options: {
+                scales: {
+                    y: {
+                        beginAtZero: true
+                    }
+                }
+            }
+        });
+    }
+
+    // Search, then show what the result path returns
+    async function search(event) {
+        event.preventDefault();
+        const query = document.getElementById("searchInput").value.trim();
+        if (query === "") {
+            return;
+        }
+        const result = await fetchData(`http://127.0.0.1:8080/search?q=${encodeURIComponent(query)}`);
+        if (result.error) {
+            document.getElementById("searchData").innerText = result.error;
+            return;
+        }
+        const data = await fetchData(`http://127.0.0.1:8080${result.path}?units=eth`);
+        document.getElementById("searchData").innerText = JSON.stringify({ ...result, data }, null, 2);
+    }
+
+    document.getElementById("searchForm").addEventListener("submit", search);
+
     function toggleSpinner(visibility) {
         const spinner = document.getElementById("loadingSpinner");
         spinner.style.display = visibility ? "block" : "none";
@@ -205,20 +349,32 @@ let first = 1;
         if (first == 1) {
             toggleSpinner(true); // Show the spinner
             first = 0;
+            await displayData();
+            subscribeHeads();
         }
 
+        // Per block and per minute reductions of the latest stored blocks are computed by the server
+        const range = await fetchChartRange();
+        const blockSeries = await fetchSeriesData(range, "block");
+        const minuteSeries = await fetchSeriesData(range, "minute");
+        const burnData = await fetchBurnData(range);
 
-        await displayData();
-
-        const historicData = await fetchHistoricData();
-        if (chart) {
-            chart.destroy();
+        // A canvas can only hold one chart, free all of them before drawing again
+        for (const previous of [chart, chart2, chart3, chart4, chart5, chart6, chart7, chart8, chart9]) {
+            if (previous) {
+                previous.destroy();
+            }
         }
-        createTransactionChart(historicData);
-        createAvgGasChart(historicData);
-        createGasChart(historicData);
-        createTxsValueChart(historicData);
-        createTransactionValueChart(historicData);
+
+        createTransactionChart(blockSeries);
+        createAvgGasChart(blockSeries);
+        createGasChart(blockSeries);
+        createTxsValueChart(blockSeries);
+        createTransactionValueChart(minuteSeries);
+        createWithdrawalsChart(blockSeries);
+        createBlobGasChart(blockSeries);
+        createBlobFeeChart(blockSeries);
+        createBurnChart(burnData);
 
         toggleSpinner(false); // Hide the spinner
     }
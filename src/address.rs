@@ -0,0 +1,150 @@
+use crate::db::Db;
+use crate::eth_explore::BlockTransaction;
+use crate::internal_txs::InternalTransfer;
+use crate::metrics::MeteredHttp;
+use crate::{error::Error::*, Result};
+
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+use utoipa::ToSchema;
+
+use ethers::providers::{Middleware, Provider};
+use ethers::types::{U64, U256, Address};
+
+// Largest page of `/address/{addr}/transactions` and `/address/{addr}/internal`
+pub const MAX_PAGE_SIZE: u64 = 100;
+pub const DEFAULT_PAGE_SIZE: u64 = 25;
+
+/// Direction of the transactions relative to the address
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    #[default]
+    All,
+    /// Received from another address
+    In,
+    /// Sent to another address (or contract creation)
+    Out,
+    /// Sent to itself
+    #[serde(rename = "self")]
+    SelfTransfer,
+}
+
+/// Activity of an address in the stored blocks
+#[derive(Serialize, Deserialize, Debug, Clone, Default, ToSchema)]
+pub struct AddressActivity {
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = Option<String>, format = "quantity")]
+    pub first_seen_block: Option<U64>,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = Option<String>, format = "quantity")]
+    pub last_seen_block: Option<U64>,
+    pub transaction_count: u64,
+    pub sent_count: u64,
+    pub received_count: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct AddressSummary {
+    #[schema(value_type = String)]
+    pub address: Address,
+    /// Current balance (wei)
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub eth_balance: U256,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub nonce: U256,
+    pub is_contract: bool,
+    pub code_size: u64,
+    #[serde(flatten)]
+    pub activity: AddressActivity,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct AddressTransactions {
+    #[schema(value_type = String)]
+    pub address: Address,
+    pub direction: Direction,
+    pub page: u64,
+    pub page_size: u64,
+    pub total: u64,
+    /// Newest first
+    pub transactions: Vec<BlockTransaction>,
+}
+
+/// Balance, nonce and code from the RPC, activity from the database
+pub async fn get_address_summary(provider: Arc<Provider<MeteredHttp>>, db: &Db, address: Address) -> Result<AddressSummary> {
+    let eth_balance = provider.get_balance(address, None).await.map_err(EthProviderErr)?;
+    let nonce = provider.get_transaction_count(address, None).await.map_err(EthProviderErr)?;
+    let code = provider.get_code(address, None).await.map_err(EthProviderErr)?;
+    let activity = db.fetch_address_activity(address).await?;
+    Ok(AddressSummary {
+        address,
+        eth_balance,
+        nonce,
+        is_contract: !code.is_empty(),
+        code_size: code.len() as u64,
+        activity,
+    })
+}
+
+// Number of transactions before page `page`, Mongo takes an i64 `$skip`
+fn page_offset(page: u64, page_size: u64) -> Result<u64> {
+    if page_size == 0 || page_size > MAX_PAGE_SIZE {
+        return Err(InvalidParam(format!("page size {} (1..={})", page_size, MAX_PAGE_SIZE)));
+    }
+    page.checked_mul(page_size)
+        .filter(|skip| *skip <= i64::MAX as u64)
+        .ok_or_else(|| InvalidParam(format!("page {}", page)))
+}
+
+/// Page `page` (from 0) of the internal transfers from or to `address`, newest first
+pub async fn get_address_internal_transfers(
+    db: &Db, 
+    address: Address, 
+    page: u64, 
+    page_size: u64
+) -> Result<Vec<InternalTransfer>> {
+    let skip = page_offset(page, page_size)?;
+    db.fetch_internal_transfers_by_address(address, skip, page_size).await
+}
+
+/// Page `page` (from 0) of the stored transactions of `address`
+pub async fn get_address_transactions(
+    db: &Db, 
+    address: Address, 
+    direction: Direction, 
+    page: u64, 
+    page_size: u64
+) -> Result<AddressTransactions> {
+    let skip = page_offset(page, page_size)?;
+    let (total, transactions) = db
+        .fetch_transactions_by_address(address, direction, skip, page_size)
+        .await?;
+    Ok(AddressTransactions {
+        address,
+        direction,
+        page,
+        page_size,
+        total,
+        transactions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_offsets() {
+        assert_eq!(page_offset(0, 25).unwrap(), 0);
+        assert_eq!(page_offset(3, 25).unwrap(), 75);
+        assert!(matches!(page_offset(0, 0), Err(InvalidParam(_))));
+        assert!(matches!(page_offset(0, MAX_PAGE_SIZE + 1), Err(InvalidParam(_))));
+        // Overflows u64, then above the i64 `$skip` of Mongo
+        assert!(matches!(page_offset(u64::MAX, 2), Err(InvalidParam(_))));
+        assert!(matches!(page_offset(i64::MAX as u64, 2), Err(InvalidParam(_))));
+        assert_eq!(page_offset(i64::MAX as u64, 1).unwrap(), i64::MAX as u64);
+    }
+}
@@ -0,0 +1,53 @@
+use crate::{error::Error::*, Result};
+
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::de::DeserializeOwned;
+
+/// Environment variable `name` parsed as `T`, `default` when it is not set
+pub fn var<T: FromStr>(name: &str, default: T) -> Result<T> {
+    match std::env::var(name) {
+        Ok(value) => value.trim()
+            .parse()
+            .map_err(|_| ConfigError(format!("{}={}", name, value))),
+        Err(_) => Ok(default),
+    }
+}
+
+/// JSON file whose path is the environment variable `name`, `None` when it is not set
+pub fn json_file<T: DeserializeOwned>(name: &str) -> Result<Option<T>> {
+    let Ok(path) = std::env::var(name) else {
+        return Ok(None);
+    };
+    let json = std::fs::read_to_string(&path)
+        .map_err(|err| ConfigError(format!("{}={}: {}", name, path, err)))?;
+    serde_json::from_str(&json)
+        .map(Some)
+        .map_err(|err| ConfigError(format!("{}={}: {}", name, path, err)))
+}
+
+/// Current unix time (seconds)
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unset_variables_take_the_default() {
+        assert_eq!(var("ETH_EXPLORER_TEST_UNSET", 64u64).unwrap(), 64);
+    }
+
+    #[test]
+    fn set_variables_are_parsed() {
+        std::env::set_var("ETH_EXPLORER_TEST_VALID", " 128 ");
+        assert_eq!(var("ETH_EXPLORER_TEST_VALID", 64u64).unwrap(), 128);
+        std::env::set_var("ETH_EXPLORER_TEST_INVALID", "many");
+        assert!(var("ETH_EXPLORER_TEST_INVALID", 64u64).is_err());
+    }
+}
@@ -1,17 +1,62 @@
 use crate::db::bson::to_bson;
-use crate::eth_explore::{EthBlocks};
+use crate::eth_explore::{EthBlocks, BlockWithdrawal, BlockTransaction};
+use crate::nft::NftTransfer;
+use crate::tokens::TokenMetadata;
+use crate::internal_txs::InternalTransfer;
+use crate::uncles::EthUncle;
+use crate::stats::{BlockStats, BlockGasPrices};
+use crate::address::{Direction, AddressActivity};
+use crate::ens::{EnsLookup, EnsRecord};
+use crate::metrics::MongoMetrics;
 use crate::{error::Error::*, Result};
 use mongodb::results::DeleteResult;
+use mongodb::options::{FindOptions, FindOneOptions, ReplaceOptions, IndexOptions};
+use mongodb::IndexModel;
 use mongodb::{options::ClientOptions, Client, Database};
 use mongodb::bson::{self, document::Document};
-use ethers::types::{U64};
+use ethers::types::{U64, U256, H256, Address};
 use futures::stream::StreamExt;
 
 
 const DB_NAME: &str = "ethereum-blockchain";
 const COLLECTION: &str = "eth_blocks";
+const NFT_COLLECTION: &str = "nft_transfers";
+const TOKEN_COLLECTION: &str = "tokens";
+const INTERNAL_TX_COLLECTION: &str = "internal_transfers";
+const UNCLE_COLLECTION: &str = "uncles";
+const BLOCK_STATS_COLLECTION: &str = "block_stats";
+const ENS_COLLECTION: &str = "ens";
 const DB_URL: &str = "mongodb://localhost:27017";
 
+/// Filter on the blocks `from..=to`. Block numbers are stored as hex strings, which
+/// sort like the numbers only when they have the same length: the range is split
+/// into one indexed string range per number of hex digits.
+fn hex_number_range(from: u64, to: u64) -> Document {
+    let mut ranges: Vec<Document> = vec![];
+    let mut low = from;
+    while low <= to {
+        let digits = format!("{:x}", low).len();
+        let last = if digits >= 16 { u64::MAX } else { (1u64 << (4 * digits)) - 1 };
+        let high = to.min(last);
+        ranges.push(bson::doc! {
+            "number": {
+                "$gte": format!("0x{:x}", low),
+                "$lte": format!("0x{:x}", high),
+                "$regex": format!("^0x[0-9a-f]{{{}}}$", digits),
+            }
+        });
+        if high == u64::MAX {
+            break;
+        }
+        low = high + 1;
+    }
+    match ranges.len() {
+        0 => bson::doc! { "number": { "$in": [] } },
+        1 => ranges.remove(0),
+        _ => bson::doc! { "$or": ranges },
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Db {
     db: Database,
@@ -20,7 +65,8 @@ pub struct Db {
 impl Db {
 
     pub async fn init() -> Result<Self> {
-        let client_options = ClientOptions::parse(DB_URL).await?;
+        let mut client_options = ClientOptions::parse(DB_URL).await?;
+        client_options.command_event_handler = Some(std::sync::Arc::new(MongoMetrics));
         let client = Client::with_options(client_options)?;
         let db = client.database(DB_NAME);
         Ok(Self { db })
@@ -30,9 +76,72 @@ impl Db {
         self.db.collection::<bson::Document>(COLLECTION)
     }
 
+    fn get_nft_collection(&self) -> mongodb::Collection<bson::Document> {
+        self.db.collection::<bson::Document>(NFT_COLLECTION)
+    }
+
+    fn get_token_collection(&self) -> mongodb::Collection<bson::Document> {
+        self.db.collection::<bson::Document>(TOKEN_COLLECTION)
+    }
+
+    fn get_internal_tx_collection(&self) -> mongodb::Collection<bson::Document> {
+        self.db.collection::<bson::Document>(INTERNAL_TX_COLLECTION)
+    }
+
+    fn get_uncle_collection(&self) -> mongodb::Collection<bson::Document> {
+        self.db.collection::<bson::Document>(UNCLE_COLLECTION)
+    }
+
+    fn get_block_stats_collection(&self) -> mongodb::Collection<bson::Document> {
+        self.db.collection::<bson::Document>(BLOCK_STATS_COLLECTION)
+    }
+
+    fn get_ens_collection(&self) -> mongodb::Collection<bson::Document> {
+        self.db.collection::<bson::Document>(ENS_COLLECTION)
+    }
+
+    pub async fn ping(&self) -> Result<()> {
+        self.db
+            .run_command(bson::doc! { "ping": 1 }, None)
+            .await
+            .map_err(MongoQueryError)?;
+        Ok(())
+    }
+
+    /// Indexes of the lookups by hash and address
+    pub async fn create_indexes(&self) -> Result<()> {
+        let keys = [
+            bson::doc! { "number": 1 },
+            bson::doc! { "hash": 1 },
+            bson::doc! { "transactions.hash": 1 },
+            bson::doc! { "transactions.from": 1 },
+            bson::doc! { "transactions.to": 1 },
+        ];
+        let indexes = keys.into_iter()
+            .map(|keys| IndexModel::builder().keys(keys).build())
+            .collect::<Vec<IndexModel>>();
+        self.get_collection()
+            .create_indexes(indexes, None)
+            .await
+            .map_err(MongoQueryError)?;
+        let index = IndexModel::builder()
+            .keys(bson::doc! { "number": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        self.get_block_stats_collection()
+            .create_index(index, None)
+            .await
+            .map_err(MongoQueryError)?;
+        Ok(())
+    }
+
     pub async fn delete_collection(&self) -> Result<DeleteResult> {
         let filter = bson::doc! {};
         let result = self.get_collection()
+            .delete_many(filter.clone(), None)
+            .await
+            .map_err(MongoQueryError)?;
+        self.get_block_stats_collection()
             .delete_many(filter, None)
             .await
             .map_err(MongoQueryError)?;
@@ -56,16 +165,40 @@ impl Db {
         }
     }
 
+    pub async fn fetch_ethblocks_by_hash(&self, hash: H256) -> Result<Option<EthBlocks>> {
+        let filter = bson::doc! { "hash": to_bson(&hash).map_err(MongoBsonSerError)? };
+        let result = self.get_collection()
+            .find_one(filter, None)
+            .await?;
+
+        match result {
+            Some(doc) => Ok(Some(self.doc_to_ethblocks(doc).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Block containing the transaction `hash`
+    pub async fn fetch_ethblocks_by_transaction(&self, hash: H256) -> Result<Option<EthBlocks>> {
+        let filter = bson::doc! { "transactions.hash": to_bson(&hash).map_err(MongoBsonSerError)? };
+        let result = self.get_collection()
+            .find_one(filter, None)
+            .await?;
+
+        match result {
+            Some(doc) => Ok(Some(self.doc_to_ethblocks(doc).await?)),
+            None => Ok(None),
+        }
+    }
+
     pub async fn create_ethblocks(&self, eth_blocks: &EthBlocks) -> Result<()> {
         let doc = bson::to_document(&eth_blocks).unwrap();
         self.get_collection()
             .insert_one(doc, None)
             .await
             .map_err(MongoQueryError)?;
-        Ok(())
+        self.save_block_stats(&BlockStats::from_block(eth_blocks)).await
     }
     
-    #[allow(dead_code)]
     pub async fn edit_ethblocks(&self, eth_blocks: &EthBlocks) -> Result<()> {
         let block_number_hex = format!("0x{:x}", eth_blocks.number.unwrap());
         let filter = bson::doc! { "number": block_number_hex };
@@ -75,7 +208,7 @@ impl Db {
             .find_one_and_replace(filter, doc, None)
             .await
             .map_err(MongoQueryError)?;
-        Ok(())
+        self.save_block_stats(&BlockStats::from_block(eth_blocks)).await
     }
 
     pub async fn found_one_ethblocks(&self, block_number: U64) -> bool {
@@ -104,7 +237,22 @@ impl Db {
             .delete_one(filter, None)
             .await
             .map_err(MongoQueryError)?;
-        Ok(())
+        self.delete_block_stats(block_number.as_u64()).await
+    }
+
+    /// Blocks `from..=to`
+    pub async fn fetch_ethblocks_range(&self, from: u64, to: u64, with_transactions: bool) -> Result<Vec<EthBlocks>> {
+        let filter = hex_number_range(from, to);
+        let projection = if with_transactions { None } else { Some(bson::doc! { "transactions": 0 }) };
+        let options = FindOptions::builder().projection(projection).build();
+        let mut cursor = self.get_collection().find(filter, options).await?;
+        let mut eth_blocks: Vec<EthBlocks> = Vec::new();
+        while let Some(result) = cursor.next().await {
+            let document = result.map_err(MongoQueryError)?;
+            eth_blocks.push(self.doc_to_ethblocks(document).await?);
+        }
+        eth_blocks.sort_by_key(|block| block.number);
+        Ok(eth_blocks)
     }
 
     pub async fn fetch_all_ethblocks(&self) -> Result<Vec<EthBlocks>> {
@@ -123,19 +271,425 @@ impl Db {
     
         Ok(eth_blocks)
     }
-    
-}
 
+    pub async fn create_nft_transfers(&self, transfers: &[NftTransfer]) -> Result<()> {
+        if transfers.is_empty() {
+            return Ok(());
+        }
+        let docs = transfers.iter()
+            .map(bson::to_document)
+            .collect::<std::result::Result<Vec<Document>, _>>()
+            .map_err(MongoBsonSerError)?;
+        self.get_nft_collection()
+            .insert_many(docs, None)
+            .await
+            .map_err(MongoQueryError)?;
+        Ok(())
+    }
+
+    pub async fn fetch_nft_transfers(&self, contract: Address, token_id: U256) -> Result<Vec<NftTransfer>> {
+        let filter = bson::doc! {
+            "contract": to_bson(&contract).map_err(MongoBsonSerError)?,
+            "token_id": to_bson(&token_id).map_err(MongoBsonSerError)?,
+        };
+        let mut cursor = self.get_nft_collection().find(filter, None).await?;
+        let mut transfers: Vec<NftTransfer> = Vec::new();
+        while let Some(result) = cursor.next().await {
+            let document = result.map_err(MongoQueryError)?;
+            transfers.push(bson::from_document(document)?);
+        }
+        Ok(transfers)
+    }
+
+    pub async fn fetch_token(&self, address: Address) -> Result<Option<TokenMetadata>> {
+        let filter = bson::doc! { "address": to_bson(&address).map_err(MongoBsonSerError)? };
+        let result = self.get_token_collection()
+            .find_one(filter, None)
+            .await
+            .map_err(MongoQueryError)?;
+        match result {
+            Some(doc) => Ok(Some(bson::from_document(doc)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn save_token(&self, token: &TokenMetadata) -> Result<()> {
+        let filter = bson::doc! { "address": to_bson(&token.address).map_err(MongoBsonSerError)? };
+        let doc = bson::to_document(token).map_err(MongoBsonSerError)?;
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.get_token_collection()
+            .replace_one(filter, doc, options)
+            .await
+            .map_err(MongoQueryError)?;
+        Ok(())
+    }
+
+    // Forward records are keyed by name, reverse records by address
+    fn ens_filter(lookup: EnsLookup, name: Option<&str>, address: Option<Address>) -> Result<Document> {
+        let lookup = to_bson(&lookup).map_err(MongoBsonSerError)?;
+        Ok(match (name, address) {
+            (Some(name), _) => bson::doc! { "lookup": lookup, "name": name },
+            (None, address) => bson::doc! { "lookup": lookup, "address": to_bson(&address).map_err(MongoBsonSerError)? },
+        })
+    }
+
+    pub async fn fetch_ens(&self, lookup: EnsLookup, name: Option<&str>, address: Option<Address>) -> Result<Option<EnsRecord>> {
+        let filter = Self::ens_filter(lookup, name, address)?;
+        let result = self.get_ens_collection()
+            .find_one(filter, None)
+            .await
+            .map_err(MongoQueryError)?;
+        match result {
+            Some(doc) => Ok(Some(bson::from_document(doc)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn save_ens(&self, record: &EnsRecord) -> Result<()> {
+        let filter = match record.lookup {
+            EnsLookup::Forward => Self::ens_filter(record.lookup, record.name.as_deref(), None)?,
+            EnsLookup::Reverse => Self::ens_filter(record.lookup, None, record.address)?,
+        };
+        let doc = bson::to_document(record).map_err(MongoBsonSerError)?;
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.get_ens_collection()
+            .replace_one(filter, doc, options)
+            .await
+            .map_err(MongoQueryError)?;
+        Ok(())
+    }
+
+    pub async fn create_internal_transfers(&self, transfers: &[InternalTransfer]) -> Result<()> {
+        if transfers.is_empty() {
+            return Ok(());
+        }
+        let docs = transfers.iter()
+            .map(bson::to_document)
+            .collect::<std::result::Result<Vec<Document>, _>>()
+            .map_err(MongoBsonSerError)?;
+        self.get_internal_tx_collection()
+            .insert_many(docs, None)
+            .await
+            .map_err(MongoQueryError)?;
+        Ok(())
+    }
+
+    pub async fn delete_internal_transfers(&self, block_number: u64) -> Result<DeleteResult> {
+        let filter = bson::doc! { "block_number": block_number as i64 };
+        let result = self.get_internal_tx_collection()
+            .delete_many(filter, None)
+            .await
+            .map_err(MongoQueryError)?;
+        Ok(result)
+    }
+
+    /// Page `skip..skip + limit` (newest first) of the internal transfers from or to `address`
+    pub async fn fetch_internal_transfers_by_address(
+        &self, 
+        address: Address, 
+        skip: u64, 
+        limit: u64
+    ) -> Result<Vec<InternalTransfer>> {
+        let address = to_bson(&address).map_err(MongoBsonSerError)?;
+        let filter = bson::doc! { "$or": [ { "from": address.clone() }, { "to": address } ] };
+        let options = FindOptions::builder()
+            .sort(bson::doc! { "block_number": -1, "transaction_hash": 1, "trace_index": 1 })
+            .skip(skip)
+            .limit(limit as i64)
+            .build();
+        let mut cursor = self.get_internal_tx_collection().find(filter, options).await?;
+        let mut transfers: Vec<InternalTransfer> = Vec::new();
+        while let Some(result) = cursor.next().await {
+            let document = result.map_err(MongoQueryError)?;
+            transfers.push(bson::from_document(document)?);
+        }
+        Ok(transfers)
+    }
+
+    pub async fn fetch_withdrawals_by_address(&self, address: Address) -> Result<Vec<BlockWithdrawal>> {
+        let address = to_bson(&address).map_err(MongoBsonSerError)?;
+        let pipeline = vec![
+            bson::doc! { "$match": { "withdrawals.address": address.clone() } },
+            bson::doc! { "$unwind": "$withdrawals" },
+            bson::doc! { "$match": { "withdrawals.address": address } },
+            bson::doc! { "$project": {
+                "_id": 0,
+                "block_number": "$number",
+                "timestamp": 1,
+                "index": "$withdrawals.index",
+                "validator_index": "$withdrawals.validator_index",
+                "address": "$withdrawals.address",
+                "amount": "$withdrawals.amount",
+            } },
+        ];
+        let mut cursor = self.get_collection()
+            .aggregate(pipeline, None)
+            .await
+            .map_err(MongoQueryError)?;
+        let mut withdrawals: Vec<BlockWithdrawal> = Vec::new();
+        while let Some(result) = cursor.next().await {
+            let document = result.map_err(MongoQueryError)?;
+            withdrawals.push(bson::from_document(document)?);
+        }
+        // Hex strings do not sort numerically in MongoDB
+        withdrawals.sort_by_key(|w| std::cmp::Reverse(w.withdrawal.index));
+        Ok(withdrawals)
+    }
+
+    // Transactions of `address` in the given direction, one document per transaction
+    fn address_transactions_pipeline(address: &bson::Bson, direction: Direction) -> Vec<Document> {
+        let from = bson::doc! { "transactions.from": address.clone() };
+        let to = bson::doc! { "transactions.to": address.clone() };
+        let not_from = bson::doc! { "transactions.from": { "$ne": address.clone() } };
+        let not_to = bson::doc! { "transactions.to": { "$ne": address.clone() } };
+        let filter = match direction {
+            Direction::All => bson::doc! { "$or": [from.clone(), to.clone()] },
+            Direction::In => bson::doc! { "$and": [to.clone(), not_from] },
+            Direction::Out => bson::doc! { "$and": [from.clone(), not_to] },
+            Direction::SelfTransfer => bson::doc! { "$and": [from.clone(), to.clone()] },
+        };
+        vec![
+            bson::doc! { "$match": { "$or": [from, to] } },
+            bson::doc! { "$unwind": { "path": "$transactions", "includeArrayIndex": "transaction_index" } },
+            bson::doc! { "$match": filter },
+        ]
+    }
+
+    /// First / last block and counts of the stored transactions of `address`
+    pub async fn fetch_address_activity(&self, address: Address) -> Result<AddressActivity> {
+        let address = to_bson(&address).map_err(MongoBsonSerError)?;
+        let mut pipeline = Self::address_transactions_pipeline(&address, Direction::All);
+        // Timestamps are hex strings of the same length, so they sort in time order
+        pipeline.push(bson::doc! { "$sort": { "timestamp": 1, "transaction_index": 1 } });
+        pipeline.push(bson::doc! { "$group": {
+            "_id": bson::Bson::Null,
+            "first_seen_block": { "$first": "$number" },
+            "last_seen_block": { "$last": "$number" },
+            "transaction_count": { "$sum": 1_i64 },
+            "sent_count": { "$sum": { "$cond": [{ "$eq": ["$transactions.from", address.clone()] }, 1_i64, 0_i64] } },
+            "received_count": { "$sum": { "$cond": [{ "$eq": ["$transactions.to", address] }, 1_i64, 0_i64] } },
+        } });
+        pipeline.push(bson::doc! { "$project": { "_id": 0 } });
+        let mut cursor = self.get_collection()
+            .aggregate(pipeline, None)
+            .await
+            .map_err(MongoQueryError)?;
+        match cursor.next().await {
+            Some(result) => Ok(bson::from_document(result.map_err(MongoQueryError)?)?),
+            None => Ok(AddressActivity::default()),
+        }
+    }
+
+    /// Total and page `skip..skip + limit` (newest first) of the stored transactions of `address`
+    pub async fn fetch_transactions_by_address(
+        &self, 
+        address: Address, 
+        direction: Direction, 
+        skip: u64, 
+        limit: u64
+    ) -> Result<(u64, Vec<BlockTransaction>)> {
+        let address = to_bson(&address).map_err(MongoBsonSerError)?;
+        let mut pipeline = Self::address_transactions_pipeline(&address, direction);
+        pipeline.push(bson::doc! { "$facet": {
+            "total": [{ "$group": { "_id": bson::Bson::Null, "count": { "$sum": 1_i64 } } }],
+            "transactions": [
+                { "$sort": { "timestamp": -1, "transaction_index": -1 } },
+                { "$skip": skip as i64 },
+                { "$limit": limit as i64 },
+                { "$replaceRoot": { "newRoot": { "$mergeObjects": [
+                    "$transactions",
+                    { 
+                        "block_number": "$number", 
+                        "timestamp": "$timestamp", 
+                        "transaction_index": "$transaction_index" 
+                    },
+                ] } } },
+            ],
+        } });
+        let mut cursor = self.get_collection()
+            .aggregate(pipeline, None)
+            .await
+            .map_err(MongoQueryError)?;
+        let Some(result) = cursor.next().await else {
+            return Ok((0, vec![]));
+        };
+        let document = result.map_err(MongoQueryError)?;
+        let total = document.get_array("total")
+            .ok()
+            .and_then(|total| total.first())
+            .and_then(|count| count.as_document())
+            .and_then(|count| count.get_i64("count").ok())
+            .unwrap_or_default() as u64;
+        let mut transactions: Vec<BlockTransaction> = Vec::new();
+        if let Ok(documents) = document.get_array("transactions") {
+            for transaction in documents.iter().filter_map(|transaction| transaction.as_document()) {
+                transactions.push(bson::from_document(transaction.clone())?);
+            }
+        }
+        Ok((total, transactions))
+    }
+
+    pub async fn create_uncles(&self, uncles: &[EthUncle]) -> Result<()> {
+        if uncles.is_empty() {
+            return Ok(());
+        }
+        let docs = uncles.iter()
+            .map(bson::to_document)
+            .collect::<std::result::Result<Vec<Document>, _>>()
+            .map_err(MongoBsonSerError)?;
+        self.get_uncle_collection()
+            .insert_many(docs, None)
+            .await
+            .map_err(MongoQueryError)?;
+        Ok(())
+    }
 
+    /// Uncles included by the blocks `from..=to`
+    pub async fn fetch_uncles_range(&self, from: u64, to: u64) -> Result<Vec<EthUncle>> {
+        let filter = bson::doc! { "nephew_number": { "$gte": from as i64, "$lte": to as i64 } };
+        let mut cursor = self.get_uncle_collection().find(filter, None).await?;
+        let mut uncles: Vec<EthUncle> = Vec::new();
+        while let Some(result) = cursor.next().await {
+            let document = result.map_err(MongoQueryError)?;
+            uncles.push(bson::from_document(document)?);
+        }
+        Ok(uncles)
+    }
 
+    /// NFT transfers, uncles and internal transfers indexed from the block `block_number`
+    pub async fn delete_block_details(&self, block_number: u64) -> Result<()> {
+        let filter = bson::doc! { "block_number": block_number as i64 };
+        self.get_nft_collection()
+            .delete_many(filter, None)
+            .await
+            .map_err(MongoQueryError)?;
+        let filter = bson::doc! { "nephew_number": block_number as i64 };
+        self.get_uncle_collection()
+            .delete_many(filter, None)
+            .await
+            .map_err(MongoQueryError)?;
+        self.delete_internal_transfers(block_number).await?;
+        Ok(())
+    }
 
+    pub async fn save_block_stats(&self, stats: &BlockStats) -> Result<()> {
+        let filter = bson::doc! { "number": stats.number as i64 };
+        let doc = bson::to_document(stats).map_err(MongoBsonSerError)?;
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.get_block_stats_collection()
+            .replace_one(filter, doc, options)
+            .await
+            .map_err(MongoQueryError)?;
+        Ok(())
+    }
 
+    pub async fn delete_block_stats(&self, block_number: u64) -> Result<()> {
+        let filter = bson::doc! { "number": block_number as i64 };
+        self.get_block_stats_collection()
+            .delete_one(filter, None)
+            .await
+            .map_err(MongoQueryError)?;
+        Ok(())
+    }
 
+    /// First and last stored block numbers. Hex numbers in `eth_blocks` do not sort
+    /// numerically, `block_stats` has one document per stored block with an integer
+    /// `number`: two reads of the ends of its `number` index.
+    pub async fn fetch_block_number_range(&self) -> Result<Option<(u64, u64)>> {
+        let first = self.fetch_block_stats_end(1).await?;
+        let last = self.fetch_block_stats_end(-1).await?;
+        Ok(first.zip(last))
+    }
 
+    // Lowest (`order` 1) or highest (`order` -1) number in `block_stats`
+    async fn fetch_block_stats_end(&self, order: i32) -> Result<Option<u64>> {
+        let options = FindOneOptions::builder()
+            .sort(bson::doc! { "number": order })
+            .projection(bson::doc! { "_id": 0, "number": 1 })
+            .build();
+        let document = self.get_block_stats_collection()
+            .find_one(None, options)
+            .await
+            .map_err(MongoQueryError)?;
+        Ok(document
+            .and_then(|document| document.get_i64("number").ok())
+            .map(|number| number as u64))
+    }
 
+    /// Highest block number stored in `eth_blocks` (the sync head)
+    pub async fn fetch_latest_block_number(&self) -> Result<Option<u64>> {
+        Ok(self.fetch_block_number_range().await?.map(|(_, to)| to))
+    }
 
+    pub async fn fetch_block_stats_range(&self, from: u64, to: u64) -> Result<Vec<BlockStats>> {
+        let filter = bson::doc! { "number": { "$gte": from as i64, "$lte": to as i64 } };
+        let options = FindOptions::builder().sort(bson::doc! { "number": 1 }).build();
+        let mut cursor = self.get_block_stats_collection().find(filter, options).await?;
+        let mut stats: Vec<BlockStats> = Vec::new();
+        while let Some(result) = cursor.next().await {
+            let document = result.map_err(MongoQueryError)?;
+            stats.push(bson::from_document(document)?);
+        }
+        Ok(stats)
+    }
 
+    /// Gas prices paid in the blocks `from..=to`, read from their embedded transactions
+    pub async fn fetch_block_gas_prices_range(&self, from: u64, to: u64) -> Result<Vec<BlockGasPrices>> {
+        let projection = bson::doc! {
+            "number": 1,
+            "transactions.gas_price": 1,
+            "transactions.effective_gas_price": 1,
+        };
+        let options = FindOptions::builder().projection(projection).build();
+        let mut cursor = self.get_collection().find(hex_number_range(from, to), options).await?;
+        let mut gas_prices: Vec<BlockGasPrices> = Vec::new();
+        while let Some(result) = cursor.next().await {
+            let document = result.map_err(MongoQueryError)?;
+            gas_prices.push(bson::from_document(document)?);
+        }
+        Ok(gas_prices)
+    }
 
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    // Evaluate the filter the way MongoDB compares strings
+    fn matches(filter: &Document, number: u64) -> bool {
+        let hex = format!("0x{:x}", number);
+        let ranges: Vec<&Document> = match filter.get_array("$or") {
+            Ok(ranges) => ranges.iter().filter_map(|range| range.as_document()).collect(),
+            Err(_) => vec![filter],
+        };
+        ranges.iter().any(|range| {
+            let Ok(number) = range.get_document("number") else {
+                return false;
+            };
+            let (Ok(low), Ok(high), Ok(regex)) = (number.get_str("$gte"), number.get_str("$lte"), number.get_str("$regex")) else {
+                return false;
+            };
+            let digits: usize = regex.trim_start_matches("^0x[0-9a-f]{").trim_end_matches("}$").parse().unwrap();
+            low <= hex.as_str() && hex.as_str() <= high && hex.len() == digits + 2
+        })
+    }
 
+    #[test]
+    fn hex_number_range_selects_the_range() {
+        for (from, to) in [(0, 0), (5, 5), (14, 300), (0, 4096), (255, 256), (4000, 70000)] {
+            let filter = hex_number_range(from, to);
+            for number in 0..=(to + 300) {
+                assert_eq!(matches(&filter, number), (from..=to).contains(&number), "{} in {}..={}", number, from, to);
+            }
+        }
+    }
+
+    #[test]
+    fn hex_number_range_has_one_clause_per_length() {
+        assert!(hex_number_range(0x10, 0xff).get_document("number").is_ok());
+        assert_eq!(hex_number_range(14, 300).get_array("$or").unwrap().len(), 3);
+        assert_eq!(hex_number_range(0, u64::MAX).get_array("$or").unwrap().len(), 16);
+        assert!(!matches(&hex_number_range(10, 9), 9));
+    }
+}
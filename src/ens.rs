@@ -0,0 +1,239 @@
+use crate::db::Db;
+use crate::config;
+use crate::units::{self, Units};
+use crate::metrics::MeteredHttp;
+use crate::{error::Error::*, Result};
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+use utoipa::{IntoParams, ToSchema};
+use utoipa::openapi::{RefOr, Schema, schema::{Object, ObjectBuilder, SchemaType}};
+use serde_json::{Map, Value};
+
+use actix_web::HttpResponse;
+use futures::stream::{self, StreamExt};
+use ethers::providers::{Middleware, Provider, ProviderError};
+use ethers::types::Address;
+
+// Names and primary names are re-resolved after 1 hour
+pub const ENS_CACHE_TTL: u64 = 3600;
+
+// Response fields holding an address, annotated with `<field>_ens`
+const ADDRESS_FIELDS: [&str; 9] = [
+    "from", "to", "address", "miner_author", "contract_address", "miner",
+    "contract", "operator", "owner",
+];
+
+// Reverse lookups running at the same time for one response
+const ENS_CONCURRENCY: usize = 16;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum EnsLookup {
+    /// name -> address
+    Forward,
+    /// address -> primary name
+    Reverse,
+}
+
+/// Resolution cached in the `ens` collection, `None` when nothing is set
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct EnsRecord {
+    pub lookup: EnsLookup,
+    pub name: Option<String>,
+    #[schema(value_type = Option<String>)]
+    pub address: Option<Address>,
+    /// Unix time of the last on-chain resolution
+    pub resolved_at: u64,
+    /// Served from the cache
+    #[serde(skip)]
+    pub cached: bool,
+}
+
+/// `?ens=true` adds the primary names of the addresses
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EnsQuery {
+    #[serde(default)]
+    pub ens: bool,
+}
+
+// No resolver, no record or a primary name not owned by the address
+fn unset<T>(result: std::result::Result<T, ProviderError>) -> Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(ProviderError::EnsError(_)) | Err(ProviderError::EnsNotOwned(_)) => Ok(None),
+        Err(err) => Err(EthProviderErr(err)),
+    }
+}
+
+/// Address of `name`, from the `ens` collection or resolved on-chain when unknown or outdated
+pub async fn resolve_name(provider: Arc<Provider<MeteredHttp>>, db: &Db, name: &str) -> Result<EnsRecord> {
+    let name = name.trim().to_lowercase();
+    if let Some(mut record) = db.fetch_ens(EnsLookup::Forward, Some(&name), None).await? {
+        if config::unix_now().saturating_sub(record.resolved_at) < ENS_CACHE_TTL {
+            record.cached = true;
+            return Ok(record);
+        }
+    }
+    let address = unset(provider.resolve_name(&name).await)?
+        .filter(|address| !address.is_zero());
+    let record = EnsRecord {
+        lookup: EnsLookup::Forward,
+        name: Some(name),
+        address,
+        resolved_at: config::unix_now(),
+        cached: false,
+    };
+    db.save_ens(&record).await?;
+    Ok(record)
+}
+
+/// Primary name of `address` (checked against its forward resolution), cached like `resolve_name`
+pub async fn lookup_address(provider: Arc<Provider<MeteredHttp>>, db: &Db, address: Address) -> Result<EnsRecord> {
+    if let Some(mut record) = db.fetch_ens(EnsLookup::Reverse, None, Some(address)).await? {
+        if config::unix_now().saturating_sub(record.resolved_at) < ENS_CACHE_TTL {
+            record.cached = true;
+            return Ok(record);
+        }
+    }
+    let name = unset(provider.lookup_address(address).await)?
+        .filter(|name| !name.is_empty());
+    let record = EnsRecord {
+        lookup: EnsLookup::Reverse,
+        name,
+        address: Some(address),
+        resolved_at: config::unix_now(),
+        cached: false,
+    };
+    db.save_ens(&record).await?;
+    Ok(record)
+}
+
+fn collect_addresses(value: &Value, addresses: &mut HashSet<Address>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter() {
+                if ADDRESS_FIELDS.contains(&key.as_str()) {
+                    if let Some(address) = value.as_str().and_then(|address| address.parse::<Address>().ok()) {
+                        addresses.insert(address);
+                    }
+                } else {
+                    collect_addresses(value, addresses);
+                }
+            }
+        },
+        Value::Array(values) => values.iter().for_each(|value| collect_addresses(value, addresses)),
+        _ => {},
+    }
+}
+
+fn annotate_object(object: &mut Map<String, Value>, names: &HashMap<Address, String>) {
+    let mut companions: Vec<(String, Value)> = vec![];
+    for (key, value) in object.iter_mut() {
+        if ADDRESS_FIELDS.contains(&key.as_str()) {
+            let name = value.as_str()
+                .and_then(|address| address.parse::<Address>().ok())
+                .and_then(|address| names.get(&address));
+            if let Some(name) = name {
+                companions.push((format!("{}_ens", key), Value::String(name.clone())));
+            }
+        } else {
+            annotate(value, names);
+        }
+    }
+    object.extend(companions);
+}
+
+fn annotate(value: &mut Value, names: &HashMap<Address, String>) {
+    match value {
+        Value::Object(object) => annotate_object(object, names),
+        Value::Array(values) => values.iter_mut().for_each(|value| annotate(value, names)),
+        _ => {},
+    }
+}
+
+/// Add a `<field>_ens` primary name next to the address fields of `value`.
+/// Each address is looked up once, `ENS_CONCURRENCY` at a time.
+/// Failed lookups are skipped, the response is still served.
+pub async fn annotate_names(provider: Arc<Provider<MeteredHttp>>, db: &Db, value: &mut Value) {
+    let mut addresses: HashSet<Address> = HashSet::new();
+    collect_addresses(value, &mut addresses);
+
+    let names: HashMap<Address, String> = stream::iter(addresses)
+        .map(|address| {
+            let provider = provider.clone();
+            async move { (address, lookup_address(provider, db, address).await) }
+        })
+        .buffer_unordered(ENS_CONCURRENCY)
+        .filter_map(|(address, record)| async move {
+            match record {
+                Ok(EnsRecord { name: Some(name), .. }) => Some((address, name)),
+                Ok(_) => None,
+                Err(err) => {
+                    eprintln!("Error resolving ENS name of {:?}: {}", address, err);
+                    None
+                },
+            }
+        })
+        .collect()
+        .await;
+    annotate(value, &names);
+}
+
+/// Document the `<field>_ens` names added by `?ens=true` next to the address properties of an OpenAPI object
+pub fn document_schema(object: &mut Object) {
+    let names: Vec<(String, RefOr<Schema>)> = object.properties.keys()
+        .filter(|key| ADDRESS_FIELDS.contains(&key.as_str()))
+        .map(|key| {
+            let name = ObjectBuilder::new()
+                .schema_type(SchemaType::String)
+                .description(Some(format!("Primary ENS name of `{}`, with `?ens=true` (left out without one)", key)))
+                .build();
+            (format!("{}_ens", key), name.into())
+        })
+        .collect();
+    object.properties.extend(names);
+}
+
+/// `200 OK` JSON response with the quantities in `units` and, when `ens`, the primary names
+pub async fn json<T: Serialize>(provider: Arc<Provider<MeteredHttp>>, db: &Db, body: &T, units: Units, ens: bool) -> HttpResponse {
+    let mut value = units::to_json(body, units);
+    if ens {
+        annotate_names(provider, db, &mut value).await;
+    }
+    HttpResponse::Ok().json(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn addresses_are_collected_once_and_annotated() {
+        let alice = Address::repeat_byte(1);
+        let bob = Address::repeat_byte(2);
+        let mut value = json!({
+            "miner_author": format!("{:?}", alice),
+            "transactions": [
+                { "from": format!("{:?}", alice), "to": format!("{:?}", bob), "hash": format!("{:?}", alice) },
+                { "from": format!("{:?}", bob), "to": null },
+            ],
+            "holders": [{ "address": format!("{:?}", bob) }],
+        });
+
+        let mut addresses = HashSet::new();
+        collect_addresses(&value, &mut addresses);
+        assert_eq!(addresses, HashSet::from([alice, bob]));
+
+        let names = HashMap::from([(alice, "alice.eth".to_string())]);
+        annotate(&mut value, &names);
+        assert_eq!(value["miner_author_ens"], "alice.eth");
+        assert_eq!(value["transactions"][0]["from_ens"], "alice.eth");
+        assert!(value["transactions"][0].get("to_ens").is_none());
+        assert!(value["transactions"][0].get("hash_ens").is_none());
+        assert!(value["holders"][0].get("address_ens").is_none());
+    }
+}
@@ -1,12 +1,16 @@
 use std::num::ParseFloatError;
 
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
 use thiserror::Error;
+use serde::Serialize;
+use utoipa::ToSchema;
 use mongodb::bson; 
 use ethers::core::utils::*;
-use ethers::prelude::Http;
+use crate::metrics::MeteredHttp;
 
 
 #[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     #[error("error mongodb query: {0}")]
     MongoQueryError(mongodb::error::Error),
@@ -14,6 +18,8 @@ pub enum Error {
     MongoError(#[from] mongodb::error::Error), 
     #[error("could not access file in document: {0}")]
     MongoBsonError(#[from] bson::de::Error),
+    #[error("could not serialize document: {0}")]
+    MongoBsonSerError(bson::ser::Error),
     #[error("cannot convert: {0}")]
     EthConvErr(ConversionError),
     #[error("cannot convert string: {0}")]
@@ -21,5 +27,42 @@ pub enum Error {
     #[error("get blocks error: {0}")]
     EthProviderErr(ethers::providers::ProviderError),
     #[error("get oracle error: {0}")]
-    EthOracleErr(ethers::contract::ContractError<ethers::providers::Provider<Http>>),
-}
\ No newline at end of file
+    EthOracleErr(ethers::contract::ContractError<ethers::providers::Provider<MeteredHttp>>),
+    #[error("oracle price is stale: last update {0}s ago")]
+    OracleStalePrice(u64),
+    #[error("oracle answer is not positive: {0}")]
+    OracleInvalidAnswer(ethers::types::I256),
+    #[error("oracle round {0} is incomplete")]
+    OracleIncompleteRound(u128),
+    #[error("invalid parameter: {0}")]
+    InvalidParam(String),
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("invalid configuration: {0}")]
+    ConfigError(String),
+}
+
+/// Body of the error responses
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub error: String,
+}
+
+// Actix handlers can return `Result<_, Error>`, errors are sent back as JSON
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Error::InvalidParam(_) => StatusCode::BAD_REQUEST,
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::OracleStalePrice(_)
+            | Error::OracleInvalidAnswer(_)
+            | Error::OracleIncompleteRound(_) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code())
+            .json(ErrorBody { error: self.to_string() })
+    }
+}
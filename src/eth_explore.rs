@@ -1,4 +1,9 @@
 use crate::db::Db;
+use crate::config;
+use crate::nft;
+use crate::uncles;
+use crate::price_feeds;
+use crate::metrics::MeteredHttp;
 use crate::{error::Error::*, Result};
 
 use std::time::Duration;
@@ -6,86 +11,307 @@ use std::{
     ops::{Div, Mul},
     sync::Arc,
 };
-use eyre;
 use std::time::Instant;
 use serde::{Serialize, Deserialize};
+use utoipa::ToSchema;
 
-use ethers::providers::{Middleware, Http, Provider};
+use ethers::providers::{Middleware, Http, Ws, Provider};
 use ethers::{
     contract::abigen,
     core::{utils::format_units},
-    types::{U64, U256, I256, H256, Address, Transaction, Block}
+    types::{U64, U256, I256, H256, Address, Bytes, Transaction, TransactionReceipt, Block}
 };
 
 //const RPC_URL: &str = "https://eth-mainnet.g.alchemy.com/v2/GkJhEJRYGzTnVM0AmRZZ_TgzIesntlDR";
 const RPC_URL: &str = "https://eth.llamarpc.com";
+const WS_URL: &str = "wss://ethereum-rpc.publicnode.com";
 const ETH_DECIMALS: u32 = 18;
 const USD_PRICE_DECIMALS: u32 = 8;
-const ETH_USD_FEED: &str = "0x5f4eC3Df9cbd43714FE2740f5E3616155c5b8419";
+// Deepest chain reorganisation repaired by the ingester
+const MAX_REORG_DEPTH: u64 = 64;
+
+// EIP-4844 blob base fee
+const MIN_BLOB_BASE_FEE: u64 = 1;
+// Mainnet (activation timestamp, blob base fee update fraction): Cancun, Prague, BPO1, BPO2
+const BLOB_BASE_FEE_UPDATE_FRACTIONS: [(u64, u64); 4] = [
+    (1_710_338_135, 3_338_477),
+    (1_746_612_311, 5_007_716),
+    (1_765_290_071, 8_346_193),
+    (1_767_747_671, 11_684_671),
+];
     
 
 abigen!(
     AggregatorInterface,
     r#"[
         latestAnswer() public view virtual override returns (int256 answer)
+        latestRoundData() public view virtual override returns (uint80 roundId, int256 answer, uint256 startedAt, uint256 updatedAt, uint80 answeredInRound)
+        decimals() public view virtual override returns (uint8)
     ]"#,
 );
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct EthTransaction{
+    #[schema(value_type = String)]
     pub hash: H256,
+    #[schema(value_type = String)]
     pub from: Address,
+    #[schema(value_type = Option<String>)]
     pub to: Option<Address>,
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
     pub value: U256,
+    #[serde(serialize_with = "crate::units::wei_per_gas")]
+    #[schema(value_type = Option<String>, format = "wei-per-gas")]
     pub gas_price: Option<U256>,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
     pub gas: U256,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = Option<String>, format = "quantity")]
+    pub transaction_type: Option<U64>,
+    /// EIP-4844 (type 3) blob transactions
+    #[serde(serialize_with = "crate::units::wei_per_gas")]
+    #[schema(value_type = Option<String>, format = "wei-per-gas")]
+    pub max_fee_per_blob_gas: Option<U256>,
+    #[serde(default)]
+    #[schema(value_type = Vec<String>)]
+    pub blob_versioned_hashes: Vec<H256>,
+    /// Receipt fields (filled during ingestion)
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = Option<String>, format = "quantity")]
+    pub gas_used: Option<U256>,
+    #[serde(serialize_with = "crate::units::wei_per_gas")]
+    #[schema(value_type = Option<String>, format = "wei-per-gas")]
+    pub effective_gas_price: Option<U256>,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = Option<String>, format = "quantity")]
+    pub status: Option<U64>,
+    #[schema(value_type = Option<String>)]
+    pub contract_address: Option<Address>,
+    /// First 4 bytes of the input (function selector), `0x` without calldata.
+    /// Missing from the blocks stored before it was recorded
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub method_id: Option<Bytes>,
 }
 
 
 impl EthTransaction{
-    fn copy_transaction(transaction: &Transaction) -> EthTransaction {
+    pub fn copy_transaction(transaction: &Transaction) -> EthTransaction {
         let new_transaction: EthTransaction= EthTransaction{
             hash:transaction.hash,
             from:transaction.from,
             to:transaction.to,
             value:transaction.value,
             gas_price:transaction.gas_price,
-            gas:transaction.gas
+            gas:transaction.gas,
+            transaction_type:transaction.transaction_type,
+            max_fee_per_blob_gas:transaction.other
+                .get_deserialized("maxFeePerBlobGas")
+                .and_then(|value| value.ok()),
+            blob_versioned_hashes:transaction.other
+                .get_deserialized("blobVersionedHashes")
+                .and_then(|value| value.ok())
+                .unwrap_or_default(),
+            gas_used:None,
+            effective_gas_price:None,
+            status:None,
+            contract_address:None,
+            method_id:Some(Bytes::from(transaction.input[..transaction.input.len().min(4)].to_vec())),
         };
         new_transaction
     }
+
+    pub fn copy_receipt(&mut self, receipt: &TransactionReceipt) {
+        self.gas_used = receipt.gas_used;
+        self.effective_gas_price = receipt.effective_gas_price;
+        self.status = receipt.status;
+        self.contract_address = receipt.contract_address;
+    }
+}
+
+/// Transaction with the block it was included in
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct BlockTransaction {
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = Option<String>, format = "quantity")]
+    pub block_number: Option<U64>,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub timestamp: U256,
+    pub transaction_index: u64,
+    #[serde(flatten)]
+    pub transaction: EthTransaction,
+}
+
+/// Beacon chain withdrawal (post-Shanghai blocks)
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct EthWithdrawal{
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub index: U64,
+    #[serde(alias = "validatorIndex", serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub validator_index: U64,
+    #[schema(value_type = String)]
+    pub address: Address,
+    /// Amount in gwei
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub amount: U256,
+}
+
+/// Withdrawal with the block it was included in
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct BlockWithdrawal{
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = Option<String>, format = "quantity")]
+    pub block_number: Option<U64>,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub timestamp: U256,
+    #[serde(flatten)]
+    pub withdrawal: EthWithdrawal,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct EthBlocks{
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = Option<String>, format = "quantity")]
     pub number: Option<U64>,
+    #[schema(value_type = Option<String>)]
     pub hash: Option<H256>,
+    #[schema(value_type = Option<String>)]
+    pub parent_hash: Option<H256>,
+    #[schema(value_type = Option<String>)]
     pub miner_author: Option<Address>,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
     pub timestamp: U256,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = Option<String>, format = "quantity")]
+    pub gas_used: Option<U256>,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = Option<String>, format = "quantity")]
+    pub gas_limit: Option<U256>,
+    /// EIP-1559 base fee (wei)
+    #[serde(serialize_with = "crate::units::wei_per_gas")]
+    #[schema(value_type = Option<String>, format = "wei-per-gas")]
+    pub base_fee_per_gas: Option<U256>,
     pub transaction_number: u64,
-    pub transactions: Vec<EthTransaction>
+    #[serde(default)]
+    pub transactions: Vec<EthTransaction>,
+    #[serde(default)]
+    pub withdrawals: Vec<EthWithdrawal>,
+    #[serde(default)]
+    #[schema(value_type = Vec<String>)]
+    pub uncles: Vec<H256>,
+    /// EIP-4844 header fields
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = Option<String>, format = "quantity")]
+    pub blob_gas_used: Option<U256>,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = Option<String>, format = "quantity")]
+    pub excess_blob_gas: Option<U256>,
+    /// Blob base fee (wei) derived from `excess_blob_gas`
+    #[serde(serialize_with = "crate::units::wei_per_gas")]
+    #[schema(value_type = Option<String>, format = "wei-per-gas")]
+    pub blob_base_fee: Option<U256>,
+    /// Chainlink ETH/USD price at this block (8 decimals)
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = Option<String>, format = "quantity")]
+    pub eth_usd_price: Option<U256>,
+}
+
+// ethers keeps the fields it does not know (withdrawals...) in `other`
+fn copy_withdrawals<TX>(blocks: &Block<TX>) -> Vec<EthWithdrawal> {
+    match blocks.other.get_deserialized::<Vec<EthWithdrawal>>("withdrawals") {
+        Some(Ok(withdrawals)) => withdrawals,
+        _ => vec![],
+    }
+}
+
+/// `fake_exponential` from EIP-4844: factor * e ** (numerator / denominator)
+fn fake_exponential(factor: U256, numerator: U256, denominator: U256) -> U256 {
+    let mut i = U256::one();
+    let mut output = U256::zero();
+    let mut numerator_accum = factor * denominator;
+    while !numerator_accum.is_zero() {
+        output = output.saturating_add(numerator_accum);
+        numerator_accum = match numerator_accum.checked_mul(numerator) {
+            Some(accum) => accum / (denominator * i),
+            None => break,
+        };
+        i += U256::one();
+    }
+    output / denominator
+}
+
+/// Blob base fee of a block, the update fraction depends on the active fork
+pub fn blob_base_fee(excess_blob_gas: U256, timestamp: U256) -> Option<U256> {
+    let timestamp = timestamp.low_u64();
+    let (_, fraction) = BLOB_BASE_FEE_UPDATE_FRACTIONS.iter()
+        .rev()
+        .find(|(activation, _)| timestamp >= *activation)?;
+    Some(fake_exponential(U256::from(MIN_BLOB_BASE_FEE), excess_blob_gas, U256::from(*fraction)))
+}
+
+// Blob gas header fields (not known by ethers `Block`)
+fn copy_blob_gas<TX>(blocks: &Block<TX>) -> (Option<U256>, Option<U256>, Option<U256>) {
+    let blob_gas_used: Option<U256> = blocks.other
+        .get_deserialized("blobGasUsed")
+        .and_then(|value| value.ok());
+    let excess_blob_gas: Option<U256> = blocks.other
+        .get_deserialized("excessBlobGas")
+        .and_then(|value| value.ok());
+    let blob_base_fee = excess_blob_gas
+        .and_then(|excess_blob_gas| blob_base_fee(excess_blob_gas, blocks.timestamp));
+    (blob_gas_used, excess_blob_gas, blob_base_fee)
 }
 
 impl EthBlocks {
-    fn copy_blocks(blocks: &Block<H256>) -> EthBlocks {
+    pub fn copy_blocks(blocks: &Block<H256>) -> EthBlocks {
+        let (blob_gas_used, excess_blob_gas, blob_base_fee) = copy_blob_gas(blocks);
         let new_blocks: EthBlocks = EthBlocks { 
             number: blocks.number, 
             hash: blocks.hash, 
+            parent_hash: Some(blocks.parent_hash), 
             miner_author: blocks.author, 
             timestamp: blocks.timestamp, 
+            gas_used: Some(blocks.gas_used),
+            gas_limit: Some(blocks.gas_limit),
+            base_fee_per_gas: blocks.base_fee_per_gas,
             transaction_number: blocks.transactions.len() as u64,
-            transactions: vec![]
+            transactions: vec![],
+            withdrawals: copy_withdrawals(blocks),
+            uncles: blocks.uncles.clone(),
+            blob_gas_used,
+            excess_blob_gas,
+            blob_base_fee,
+            eth_usd_price: None,
         };
         new_blocks
     }
-    fn copy_blocks_txs(blocks: &Block<Transaction>) -> EthBlocks {
+    pub fn copy_blocks_txs(blocks: &Block<Transaction>) -> EthBlocks {
+        let (blob_gas_used, excess_blob_gas, blob_base_fee) = copy_blob_gas(blocks);
         let new_blocks: EthBlocks = EthBlocks { 
             number: blocks.number, 
             hash: blocks.hash, 
+            parent_hash: Some(blocks.parent_hash), 
             miner_author: blocks.author, 
             timestamp: blocks.timestamp, 
+            gas_used: Some(blocks.gas_used),
+            gas_limit: Some(blocks.gas_limit),
+            base_fee_per_gas: blocks.base_fee_per_gas,
             transaction_number: blocks.transactions.len() as u64,
-            transactions: vec![]
+            transactions: vec![],
+            withdrawals: copy_withdrawals(blocks),
+            uncles: blocks.uncles.clone(),
+            blob_gas_used,
+            excess_blob_gas,
+            blob_base_fee,
+            eth_usd_price: None,
         };
         new_blocks
     }
@@ -93,24 +319,142 @@ impl EthBlocks {
 
 
 // Initialize a new Http provider
-pub async fn get_eth_provider() -> Provider<Http> {
+pub async fn get_eth_provider() -> Provider<MeteredHttp> {
     // Initialize a new Http provider
-    let eth_provider = Provider::try_from(RPC_URL).unwrap();
+    let http: Http = RPC_URL.parse().unwrap();
+    let eth_provider = Provider::new(MeteredHttp::new(http));
     println!("Connecting with the provider...");
     eth_provider
 }
 
+pub async fn get_eth_ws_provider() -> Result<Provider<Ws>> {
+    // Websocket provider for the subscriptions
+    let eth_provider = Provider::<Ws>::connect(WS_URL).await.map_err(EthProviderErr)?;
+    println!("Connecting with the websocket provider...");
+    Ok(eth_provider)
+}
+
+
+/// Build the stored block with its transaction receipts and ETH/USD price
+pub async fn copy_block_details(provider: Arc<Provider<MeteredHttp>>, block: &Block<Transaction>) -> EthBlocks {
+    let block_number = block.number.unwrap_or_default().as_u64();
+
+    // Create EthTransactions
+    let mut vec_transactions: Vec<EthTransaction>= vec![];
+    for i_transaction in block.transactions.iter() {
+        vec_transactions.push(EthTransaction::copy_transaction(i_transaction));        
+    }
+    // Gas used and effective gas price from the receipts
+    match provider.get_block_receipts(block_number).await {
+        Ok(receipts) => {
+            for (transaction, receipt) in vec_transactions.iter_mut().zip(receipts.iter()) {
+                if transaction.hash == receipt.transaction_hash {
+                    transaction.copy_receipt(receipt);
+                }
+            }
+        },
+        Err(err) => eprintln!("Error downloading receipts of block {}: {}", block_number, err),
+    }
+    // Create EthBlocks
+    let mut blocks = EthBlocks::copy_blocks_txs(block);
+    blocks.transactions = vec_transactions;
+    match get_eth_usd_price_at(provider.clone(), block_number, blocks.timestamp).await {
+        Ok(price) => blocks.eth_usd_price = Some(price),
+        Err(err) => eprintln!("Error getting ETH/USD price of block {}: {}", block_number, err),
+    }
+    blocks
+}
+
+/// Index the NFT transfers and uncles of a stored block
+pub async fn index_block_details(provider: Arc<Provider<MeteredHttp>>, db: &Db, blocks: &EthBlocks) -> Result<()> {
+    let block_number = blocks.number.unwrap_or_default().as_u64();
+
+    // ERC-721 / ERC-1155 transfers of the block
+    nft::index_block_transfers(provider.clone(), db, block_number).await?;
+
+    // Uncles (pre-merge blocks only)
+    if !blocks.uncles.is_empty() {
+        uncles::index_block_uncles(
+            provider, 
+            db, 
+            block_number, 
+            blocks.hash, 
+            blocks.uncles.len()
+        ).await?;
+    }
+    Ok(())
+}
+
+/// Index the details of a block, then store it. A block whose indexing fails is
+/// not stored, so the next run downloads and indexes it again.
+pub async fn store_block(provider: Arc<Provider<MeteredHttp>>, db: &Db, blocks: &EthBlocks) -> Result<()> {
+    // Details left by a previous failed attempt
+    db.delete_block_details(blocks.number.unwrap_or_default().as_u64()).await?;
+    index_block_details(provider, db, blocks).await?;
+    db.create_ethblocks(blocks).await
+}
+
+/// Chain reorganisation: replace the stored ancestors of `blocks` that are not
+/// its ancestors anymore. Returns the number of replaced blocks.
+pub async fn handle_reorg(provider: Arc<Provider<MeteredHttp>>, db: &Db, blocks: &EthBlocks) -> Result<u64> {
+    let mut parent_hash = blocks.parent_hash;
+    let mut block_number = blocks.number.unwrap_or_default().as_u64();
+    let mut replaced: u64 = 0;
+
+    while replaced < MAX_REORG_DEPTH && block_number > 0 {
+        let Some(stored) = db.fetch_ethblocks(U64::from(block_number - 1)).await? else {
+            break;
+        };
+        if parent_hash.is_none() || stored.hash == parent_hash {
+            break;
+        }
+        let Some(block) = provider.get_block_with_txs(block_number - 1).await.map_err(EthProviderErr)? else {
+            break;
+        };
+        println!("Reorg: replacing block {} {:?}", block_number - 1, stored.hash);
+
+        let canonical = copy_block_details(provider.clone(), &block).await;
+        db.edit_ethblocks(&canonical).await?;
+        db.delete_block_details(block_number - 1).await?;
+        index_block_details(provider.clone(), db, &canonical).await?;
+
+        parent_hash = canonical.parent_hash;
+        block_number -= 1;
+        replaced += 1;
+    }
+    Ok(replaced)
+}
 
 // Download the lastest month history 
 pub async fn get_transactions_history( 
-    provider: Arc<Provider<Http>>, 
+    provider: Arc<Provider<MeteredHttp>>, 
     db: Db, 
     from: u64 
 ) -> eyre::Result<()> {
     
-    let eth_provider = Arc::new(provider);
+    let to = provider.get_block_number().await?.as_u64();
+    download_blocks(provider, db, from, to).await
+}
+
+/// Download and index the blocks `from..=to` that are not stored yet. Uncles only
+/// exist before the merge, pre-merge ranges are needed for the miner rewards.
+pub async fn download_blocks(
+    provider: Arc<Provider<MeteredHttp>>, 
+    db: Db, 
+    from: u64,
+    to: u64
+) -> eyre::Result<()> {
+    
+    let eth_provider = provider;
     let from_block_number = from;
-    let to_block_number = eth_provider.get_block_number().await?;
+
+    // Blocks past the chain head do not exist yet
+    let head = eth_provider.get_block_number().await?;
+    if from > head.as_u64() {
+        println!("Block {} is past the chain head ({})", from, head);
+        return Ok(());
+    }
+    let to_block_number = U64::from(to).min(head);
     println!(
         "Downloading {} blocks...", 
         to_block_number - from_block_number
@@ -127,29 +471,21 @@ pub async fn get_transactions_history(
 
             // Research in DB
             if !db.found_one_ethblocks(U64::from(block_number)).await {
-                let block_option: Option<Block<Transaction>> ;
 
                 // Download the block (takes a lot of time)
                 // It seems like Ethereum block is growing bigger and bigger, 
                 // There are more and more transactions inside each block (MEV bot, L2, etc.)
                 match eth_provider.get_block_with_txs(block_number).await {
-                    Ok(block) => {
-                        block_option = Some(block.unwrap());
-                        
-                        // Create EthTransactions
-                        let mut vec_transactions: Vec<EthTransaction>= vec![];
-                        if let Some(block) = block_option.as_ref()  {
-                            for i_transaction in block.transactions.iter() {
-                                vec_transactions.push(EthTransaction::copy_transaction(i_transaction));        
-                            }
-                        } else {
-                            println!("No Transactions");
-                        }
-                        // Create EthBlocks
-                        let block_option2 = block_option.unwrap().clone();
-                        let mut blocks = EthBlocks::copy_blocks_txs(&block_option2);
-                        blocks.transactions = vec_transactions;
-                        db.create_ethblocks(&blocks).await?;
+                    Ok(None) => {
+                        println!("\nBlock {} is not available from the node, stopping", block_number);
+                        return Ok(());
+                    },
+                    Ok(Some(block)) => {
+                        let blocks = copy_block_details(eth_provider.clone(), &block).await;
+
+                        // The stored parent may have been reorganised since
+                        handle_reorg(eth_provider.clone(), &db, &blocks).await?;
+                        store_block(eth_provider.clone(), &db, &blocks).await?;
                         
                         print!("{}", (8u8 as char));
                         print!(
@@ -184,23 +520,72 @@ pub async fn get_transactions_history(
     Ok(())
 }
 
-// Connect to oracle, get ETH/USD price
-fn get_oracle(client: &Arc<Provider<Http>>) -> AggregatorInterface<Provider<Http>> {
-    let address: Address = ETH_USD_FEED.parse().expect("Valid address");
-    AggregatorInterface::new(address, Arc::clone(client))
+// Connect to the ETH/USD feed of the registry, with its staleness limit
+fn get_oracle(client: &Arc<Provider<MeteredHttp>>) -> Result<(AggregatorInterface<Provider<MeteredHttp>>, u64)> {
+    let feed = price_feeds::find_feed("ETH", "USD")?;
+    Ok((AggregatorInterface::new(feed.address, Arc::clone(client)), feed.max_age()))
+}
+
+
+/// `latestRoundData()` output: (roundId, answer, startedAt, updatedAt, answeredInRound)
+pub type RoundData = (u128, I256, U256, U256, u128);
+
+/// Check a `latestRoundData()` answer: complete round, updated less than
+/// `max_age` seconds ago and positive
+pub fn check_round_data(
+    (round_id, answer, _started_at, updated_at, answered_in_round): RoundData,
+    max_age: u64,
+    now: u64
+) -> Result<U256> {
+    if updated_at.is_zero() || answered_in_round < round_id {
+        return Err(OracleIncompleteRound(round_id));
+    }
+    let age = now.saturating_sub(updated_at.low_u64());
+    if age > max_age {
+        return Err(OracleStalePrice(age));
+    }
+    if answer <= I256::zero() {
+        return Err(OracleInvalidAnswer(answer));
+    }
+    Ok(answer.into_raw())
+}
+
+/// Change the number of decimals of a fixed point amount
+pub fn scale_decimals(amount: U256, decimals: u32, to_decimals: u32) -> U256 {
+    if decimals >= to_decimals {
+        amount / U256::exp10((decimals - to_decimals) as usize)
+    } else {
+        amount * U256::exp10((to_decimals - decimals) as usize)
+    }
+}
+
+/// ETH/USD price from the Chainlink feed of the registry (8 decimals)
+pub async fn get_eth_usd_price(provider: Arc<Provider<MeteredHttp>>) -> Result<U256> {
+    let (oracle, max_age) = get_oracle(&provider)?;
+    let round_data = oracle.latest_round_data().call().await.map_err(EthOracleErr)?;
+    let decimals = oracle.decimals().call().await.map_err(EthOracleErr)?;
+    let answer = check_round_data(round_data, max_age, config::unix_now())?;
+    Ok(scale_decimals(answer, u32::from(decimals), USD_PRICE_DECIMALS))
 }
 
+/// ETH/USD price at a past block (8 decimals), the answer must be fresh at the
+/// block `timestamp`. Old blocks need an archive node.
+pub async fn get_eth_usd_price_at(provider: Arc<Provider<MeteredHttp>>, block_number: u64, timestamp: U256) -> Result<U256> {
+    let (oracle, max_age) = get_oracle(&provider)?;
+    let round_data = oracle.latest_round_data().block(block_number).call().await.map_err(EthOracleErr)?;
+    let decimals = oracle.decimals().block(block_number).call().await.map_err(EthOracleErr)?;
+    let answer = check_round_data(round_data, max_age, timestamp.low_u64())?;
+    Ok(scale_decimals(answer, u32::from(decimals), USD_PRICE_DECIMALS))
+}
 
 /// Retrieves the USD amount per gas unit, using a Chainlink price oracle.
 /// Function gets the amount of `wei` to be spent per gas unit then multiplies
 /// for the ETH USD value.
-pub async fn get_gas_price(provider: Arc<Provider<Http>>) -> Result<(f64,f64,f64)>{
+pub async fn get_gas_price(provider: Arc<Provider<MeteredHttp>>) -> Result<(f64,f64,f64)>{
 
-    let client = Arc::new(provider);
-    let oracle = get_oracle(&client);
+    let client = provider;
 
-    let usd_per_eth: I256 = oracle.latest_answer().call().await.map_err(EthOracleErr)?;
-    let usd_per_eth: U256 = U256::from(usd_per_eth.as_u128());
+    let usd_per_eth: U256 = get_eth_usd_price(client.clone()).await?;
     let wei_per_gas: U256 = client.get_gas_price().await.map_err(EthProviderErr)?;
 
     // Gas stations use to report gas price in gwei units (1 gwei = 10^9 wei)
@@ -219,109 +604,15 @@ pub async fn get_gas_price(provider: Arc<Provider<Http>>) -> Result<(f64,f64,f64
 
 /// `amount`: Number of wei per gas unit (18 decimals)
 /// `price_usd`: USD price per ETH (8 decimals)
-fn usd_value(amount: U256, price_usd: U256) -> Result<f64> {
+pub fn usd_value(amount: U256, price_usd: U256) -> Result<f64> {
     let base: U256 = U256::from(10).pow(ETH_DECIMALS.into());
     let value: U256 = amount.mul(price_usd).div(base);
     let f: String = format_units(value, USD_PRICE_DECIMALS).map_err(EthConvErr)?;
-    Ok(f.parse::<f64>().map_err(EthConvStrErr)?)
+    f.parse::<f64>().map_err(EthConvStrErr)
 }
 
 
 
-/// Get the last 10 blocks
-/// 
-/// 
-pub async fn get_last_10_eth_blocks(provider: Arc<Provider<Http>>) -> Result<Vec<EthBlocks>>{
-
-    // provider for interacting with the [Ethereum JSON RPC API]
-    let eth_provider = Arc::new(provider);
-
-    // Get the last block number
-    let to_block_number = eth_provider.get_block_number().await.map_err(EthProviderErr)?.as_u64();
-    println!("Lastest block : {}", to_block_number);
-
-    // Get the last ten blocks number
-    let from_block_number = to_block_number - 10;
-
-    // Start time
-    let start = Instant::now();
-
-    // Mutable blocks vector
-    let mut vec_blocks : Vec<EthBlocks> = vec![];
-
-    // Request the last 10 blocks
-    for block_number in from_block_number..=to_block_number {
-        match eth_provider.get_block(block_number).await.map_err(EthProviderErr)? {
-
-            Some(block) => {
-                //print_block(&block, true);
-                vec_blocks.push(EthBlocks::copy_blocks(&block));
-            },
-            None => {}
-        }
-    }
-    println!("Downloaded the 10 last blocks in {:.3}s", start.elapsed().as_secs_f64());
-    Ok(vec_blocks)
-}
-
-
-
-
-/// Get the last 10 transactions
-/// 
-/// 
-pub async fn get_last_10_eth_transactions(provider: Arc<Provider<Http>>) -> Result<Vec<EthTransaction>>{
-
-    // provider for interacting with the [Ethereum JSON RPC API]
-    let eth_provider = Arc::new(provider);
-    
-    // Get the last block number
-    let block_number = eth_provider.get_block_number()
-        .await
-        .map_err(EthProviderErr)?
-        .as_u64();
-    println!("Lastest block : {}", block_number);
-
-    // Build Vec<EthTransaction>
-    let mut vec_transactions: Vec<EthTransaction>= vec![];
-
-    // Start time
-    let start = Instant::now();
-    let mut transaction_number: usize = 0;
-
-    // Get the last block 
-    match eth_provider.get_block_with_txs(block_number)
-        .await
-        .map_err(EthProviderErr)? {
-
-        Some(block) => {
-            // Transactions[]
-            if !block.transactions.is_empty() {
-                transaction_number = block.transactions.len();
-
-                for (i,transaction) in block.transactions.iter().enumerate() {
-                    vec_transactions.push(EthTransaction::copy_transaction(transaction));
-                    if i == 9 {break;}
-                }
-            } else {
-                println!("No Transactions");
-            }
-        },
-        None => {}
-    }
-    
-    println!(
-        "Downloaded the last block ({}) with {} transactions in {:.3}s", 
-        block_number, 
-        transaction_number, 
-        start.elapsed().as_secs_f64()
-    );
-
-    Ok(vec_transactions)
-
-}
-
-
 #[allow(dead_code)]
 pub async fn fetch_transaction_history(
     db: Db, 
@@ -366,7 +657,7 @@ fn print_block(block: &Block<H256>, istrue: bool) {
         println!("Block hash: {:?}", block.hash.unwrap());
         println!("Block Autor: {:?}", block.author.unwrap());
         println!("{}", "=".repeat(50));
-        println!("");
+        println!();
     }
 }
 
@@ -388,4 +679,103 @@ pub fn print_gas_value(gwei: f64,usd_per_gas: f64,gas_value: f64) {
 // Subscribe to a websocket provider, 
 // Wait for new block (with transaction) using get_block_with_txs
 // Update DB
-// pub async fn subscribe_blocks_and_update(){}
\ No newline at end of file
+// pub async fn subscribe_blocks_and_update(){}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn withdrawals_are_read_from_the_rpc_block() {
+        let mut block: Block<H256> = Block::default();
+        assert!(copy_withdrawals(&block).is_empty());
+
+        block.other = serde_json::from_value(json!({
+            "withdrawals": [{
+                "index": "0x1a",
+                "validatorIndex": "0x3039",
+                "address": Address::repeat_byte(0x11),
+                "amount": "0xe8d4a51000"
+            }]
+        })).unwrap();
+        let withdrawals = copy_withdrawals(&block);
+        assert_eq!(withdrawals.len(), 1);
+        assert_eq!(withdrawals[0].index, U64::from(26));
+        assert_eq!(withdrawals[0].validator_index, U64::from(12345));
+        assert_eq!(withdrawals[0].address, Address::repeat_byte(0x11));
+        assert_eq!(withdrawals[0].amount, U256::from(1_000_000_000_000u64));
+    }
+
+    #[test]
+    fn fake_exponential_matches_the_eip_vectors() {
+        let vectors: [(u64, u64, u64, u64); 15] = [
+            (1, 0, 1, 1),
+            (38493, 0, 1000, 38493),
+            (0, 1234, 2345, 0),
+            (1, 2, 1, 6),
+            (1, 4, 2, 6),
+            (1, 3, 1, 16),
+            (1, 6, 2, 18),
+            (1, 4, 1, 49),
+            (1, 8, 2, 50),
+            (10, 8, 2, 542),
+            (11, 8, 2, 596),
+            (1, 5, 1, 136),
+            (1, 5, 2, 11),
+            (2, 5, 2, 23),
+            (1, 50_000_000, 2_225_652, 5_709_098_764),
+        ];
+        for (factor, numerator, denominator, expected) in vectors {
+            assert_eq!(
+                fake_exponential(factor.into(), numerator.into(), denominator.into()),
+                U256::from(expected),
+                "fake_exponential({}, {}, {})", factor, numerator, denominator
+            );
+        }
+    }
+
+    #[test]
+    fn blob_base_fee_follows_the_fork_schedule() {
+        let (cancun, cancun_fraction) = BLOB_BASE_FEE_UPDATE_FRACTIONS[0];
+        let (prague, prague_fraction) = BLOB_BASE_FEE_UPDATE_FRACTIONS[1];
+
+        // No blobs before Cancun
+        assert_eq!(blob_base_fee(U256::zero(), (cancun - 1).into()), None);
+        assert_eq!(blob_base_fee(U256::zero(), cancun.into()), Some(U256::from(MIN_BLOB_BASE_FEE)));
+
+        // e ** 1 with each fork update fraction
+        assert_eq!(blob_base_fee(cancun_fraction.into(), cancun.into()), Some(U256::from(2)));
+        assert_eq!(blob_base_fee(cancun_fraction.into(), prague.into()), Some(U256::from(1)));
+        assert_eq!(blob_base_fee(prague_fraction.into(), prague.into()), Some(U256::from(2)));
+
+        // An absurd excess does not panic
+        assert!(blob_base_fee(U256::MAX, prague.into()).is_some());
+    }
+
+    #[test]
+    fn round_data_checks() {
+        let now = 1_700_000_000;
+        let max_age = 2 * 3600;
+        let price = I256::from(200_000_000_000i64);
+        let round = |answer: I256, updated_at: u64, answered_in_round: u128| -> RoundData {
+            (10, answer, U256::from(updated_at), U256::from(updated_at), answered_in_round)
+        };
+
+        assert_eq!(check_round_data(round(price, now - 60, 10), max_age, now).unwrap(), price.into_raw());
+        assert!(matches!(
+            check_round_data(round(price, now - max_age - 1, 10), max_age, now),
+            Err(OracleStalePrice(age)) if age == max_age + 1
+        ));
+        assert!(matches!(check_round_data(round(price, now, 9), max_age, now), Err(OracleIncompleteRound(10))));
+        assert!(matches!(check_round_data(round(price, 0, 10), max_age, now), Err(OracleIncompleteRound(10))));
+        assert!(matches!(check_round_data(round(I256::zero(), now, 10), max_age, now), Err(OracleInvalidAnswer(_))));
+        assert!(matches!(check_round_data(round(-price, now, 10), max_age, now), Err(OracleInvalidAnswer(_))));
+    }
+
+    #[test]
+    fn decimals_are_rescaled() {
+        assert_eq!(scale_decimals(U256::from(123_456_789), 8, 8), U256::from(123_456_789));
+        assert_eq!(scale_decimals(U256::from(123_456_789), 8, 6), U256::from(1_234_567));
+        assert_eq!(scale_decimals(U256::from(1_234), 6, 8), U256::from(123_400));
+    }
+}
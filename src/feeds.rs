@@ -0,0 +1,205 @@
+use crate::db::Db;
+use crate::eth_explore::{EthBlocks, EthTransaction, BlockTransaction};
+use crate::metrics::MeteredHttp;
+use crate::{error::Error::*, Result};
+
+use std::sync::Arc;
+use std::time::Instant;
+use serde::{Serialize, Deserialize};
+use utoipa::{IntoParams, ToSchema};
+
+use ethers::providers::{Middleware, Provider};
+use ethers::types::U64;
+
+pub const DEFAULT_FEED_COUNT: u64 = 10;
+pub const MAX_FEED_COUNT: u64 = 100;
+// Blocks walked back to fill a transaction page (empty blocks)
+const MAX_FEED_BLOCKS: u64 = 64;
+
+/// `/blocks` and `/transactions` query parameters (`?count=&cursor=`)
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FeedQuery {
+    pub count: Option<u64>,
+    /// `next_cursor` of the previous page ("load more")
+    pub cursor: Option<String>,
+}
+
+/// Newest first
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct BlockFeed {
+    pub blocks: Vec<EthBlocks>,
+    /// Number of the oldest returned block, the next page starts below it
+    pub next_cursor: Option<String>,
+}
+
+/// Newest first
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct TransactionFeed {
+    /// Fewer than `count` when 64 blocks were walked without filling the page,
+    /// the next page continues from `next_cursor`
+    pub transactions: Vec<BlockTransaction>,
+    /// `<block number>-<transaction index>` of the oldest returned transaction
+    pub next_cursor: Option<String>,
+}
+
+fn check_count(count: u64) -> Result<u64> {
+    if count == 0 || count > MAX_FEED_COUNT {
+        return Err(InvalidParam(format!("count {} (1..={})", count, MAX_FEED_COUNT)));
+    }
+    Ok(count)
+}
+
+fn parse_block_cursor(cursor: &str) -> Result<u64> {
+    cursor.parse::<u64>().map_err(|_| InvalidParam(format!("cursor {}", cursor)))
+}
+
+fn parse_transaction_cursor(cursor: &str) -> Result<(u64, u64)> {
+    let invalid = || InvalidParam(format!("cursor {}", cursor));
+    let (block_number, index) = cursor.split_once('-').ok_or_else(invalid)?;
+    Ok((
+        block_number.parse::<u64>().map_err(|_| invalid())?,
+        index.parse::<u64>().map_err(|_| invalid())?,
+    ))
+}
+
+/// `count` blocks (without transactions) below the cursor or from the head, newest first.
+/// Stored blocks are read from the database, the others downloaded.
+pub async fn get_latest_blocks(
+    provider: Arc<Provider<MeteredHttp>>, 
+    db: &Db, 
+    count: u64, 
+    cursor: Option<&str>
+) -> Result<BlockFeed> {
+    let count = check_count(count)?;
+    let to = match cursor {
+        Some(cursor) => match parse_block_cursor(cursor)?.checked_sub(1) {
+            Some(to) => to,
+            None => return Ok(BlockFeed { blocks: vec![], next_cursor: None }),
+        },
+        None => provider.get_block_number().await.map_err(EthProviderErr)?.as_u64(),
+    };
+    let from = to.saturating_sub(count - 1);
+
+    // Start time
+    let start = Instant::now();
+
+    let mut stored = db.fetch_ethblocks_range(from, to, false).await?;
+    let mut blocks: Vec<EthBlocks> = Vec::with_capacity(count as usize);
+    for block_number in (from..=to).rev() {
+        match stored.iter().position(|block| block.number == Some(U64::from(block_number))) {
+            Some(position) => blocks.push(stored.swap_remove(position)),
+            None => {
+                if let Some(block) = provider.get_block(block_number).await.map_err(EthProviderErr)? {
+                    blocks.push(EthBlocks::copy_blocks(&block));
+                }
+            },
+        }
+    }
+    println!("Got the {} latest blocks in {:.3}s", blocks.len(), start.elapsed().as_secs_f64());
+
+    let next_cursor = (from > 0).then(|| from.to_string());
+    Ok(BlockFeed { blocks, next_cursor })
+}
+
+// Block with its transactions, from the database or downloaded
+async fn get_block_transactions(provider: Arc<Provider<MeteredHttp>>, db: &Db, block_number: u64) -> Result<Option<EthBlocks>> {
+    if let Some(block) = db.fetch_ethblocks(U64::from(block_number)).await? {
+        return Ok(Some(block));
+    }
+    let Some(block) = provider.get_block_with_txs(block_number).await.map_err(EthProviderErr)? else {
+        return Ok(None);
+    };
+    let mut blocks = EthBlocks::copy_blocks_txs(&block);
+    blocks.transactions = block.transactions.iter().map(EthTransaction::copy_transaction).collect();
+    Ok(Some(blocks))
+}
+
+/// `count` transactions before the cursor or from the head, newest first,
+/// walking back across at most `MAX_FEED_BLOCKS` blocks
+pub async fn get_latest_transactions(
+    provider: Arc<Provider<MeteredHttp>>, 
+    db: &Db, 
+    count: u64, 
+    cursor: Option<&str>
+) -> Result<TransactionFeed> {
+    let count = check_count(count)?;
+    let (mut block_number, mut before_index) = match cursor {
+        Some(cursor) => {
+            let (block_number, index) = parse_transaction_cursor(cursor)?;
+            (block_number, Some(index))
+        },
+        None => (provider.get_block_number().await.map_err(EthProviderErr)?.as_u64(), None),
+    };
+
+    // Start time
+    let start = Instant::now();
+
+    let mut transactions: Vec<BlockTransaction> = Vec::with_capacity(count as usize);
+    let mut walked: u64 = 0;
+    let mut exhausted = false;
+    while (transactions.len() as u64) < count && walked < MAX_FEED_BLOCKS {
+        if let Some(block) = get_block_transactions(provider.clone(), db, block_number).await? {
+            let end = before_index.unwrap_or(block.transactions.len() as u64).min(block.transactions.len() as u64);
+            for index in (0..end).rev() {
+                if transactions.len() as u64 == count {
+                    break;
+                }
+                transactions.push(BlockTransaction {
+                    block_number: block.number,
+                    timestamp: block.timestamp,
+                    transaction_index: index,
+                    transaction: block.transactions[index as usize].clone(),
+                });
+            }
+        }
+        if transactions.len() as u64 == count {
+            break;
+        }
+        let Some(previous) = block_number.checked_sub(1) else {
+            exhausted = true;
+            break;
+        };
+        block_number = previous;
+        before_index = None;
+        walked += 1;
+    }
+    println!(
+        "Got the {} latest transactions from {} blocks in {:.3}s", 
+        transactions.len(), 
+        walked + 1, 
+        start.elapsed().as_secs_f64()
+    );
+
+    let next_cursor = match transactions.last() {
+        Some(last) => Some(format!("{}-{}", last.block_number.unwrap_or_default(), last.transaction_index)),
+        // Only empty blocks walked, continue below them
+        None if !exhausted => Some(format!("{}-0", block_number + 1)),
+        None => None,
+    };
+    Ok(TransactionFeed { transactions, next_cursor })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts() {
+        assert_eq!(check_count(1).unwrap(), 1);
+        assert_eq!(check_count(MAX_FEED_COUNT).unwrap(), MAX_FEED_COUNT);
+        assert!(matches!(check_count(0), Err(InvalidParam(_))));
+        assert!(matches!(check_count(MAX_FEED_COUNT + 1), Err(InvalidParam(_))));
+    }
+
+    #[test]
+    fn cursors() {
+        assert_eq!(parse_block_cursor("17000000").unwrap(), 17_000_000);
+        assert_eq!(parse_transaction_cursor("17000000-42").unwrap(), (17_000_000, 42));
+        assert_eq!(parse_transaction_cursor("0-0").unwrap(), (0, 0));
+        for cursor in ["", "-1", "17000000", "17000000-", "-42", "a-1", "1-2-3"] {
+            assert!(matches!(parse_transaction_cursor(cursor), Err(InvalidParam(_))), "{}", cursor);
+        }
+        assert!(matches!(parse_block_cursor("0x10"), Err(InvalidParam(_))));
+    }
+}
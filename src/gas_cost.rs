@@ -0,0 +1,229 @@
+use crate::eth_explore;
+use crate::gas_oracle;
+use crate::metrics::MeteredHttp;
+use crate::{error::Error::*, Result};
+
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+use utoipa::ToSchema;
+
+use ethers::providers::{Middleware, Provider};
+use ethers::{
+    core::utils::format_units,
+    types::{U256, Address, Bytes, BlockNumber, Eip1559TransactionRequest}
+};
+
+// Contract creation: 21000 base + 32000 CREATE, then per byte of code
+// 200 gas of code deposit and 16 gas of (non-zero) calldata
+const CREATE_BASE_GAS: u64 = 53000;
+const CREATE_GAS_PER_BYTE: u64 = 216;
+/// EIP-170 contract code size limit (bytes)
+pub const MAX_CODE_SIZE: u64 = 24_576;
+
+/// Typical gas usage of common operations
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GasPreset {
+    EthTransfer,
+    Erc20Transfer,
+    UniswapSwap,
+    NftMint,
+    ContractDeploy,
+}
+
+impl GasPreset {
+    pub const ALL: [GasPreset; 5] = [
+        GasPreset::EthTransfer,
+        GasPreset::Erc20Transfer,
+        GasPreset::UniswapSwap,
+        GasPreset::NftMint,
+        GasPreset::ContractDeploy,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GasPreset::EthTransfer => "eth_transfer",
+            GasPreset::Erc20Transfer => "erc20_transfer",
+            GasPreset::UniswapSwap => "uniswap_swap",
+            GasPreset::NftMint => "nft_mint",
+            GasPreset::ContractDeploy => "contract_deploy",
+        }
+    }
+
+    /// `bytecode_size` is only used by `ContractDeploy`, at most `MAX_CODE_SIZE` bytes
+    pub fn gas(&self, bytecode_size: u64) -> Result<U256> {
+        Ok(U256::from(match self {
+            GasPreset::EthTransfer => 21_000,
+            GasPreset::Erc20Transfer => 65_000,
+            GasPreset::UniswapSwap => 180_000,
+            GasPreset::NftMint => 150_000,
+            GasPreset::ContractDeploy => {
+                if bytecode_size > MAX_CODE_SIZE {
+                    return Err(InvalidParam(format!(
+                        "bytecode_size {} (at most {} bytes)", bytecode_size, MAX_CODE_SIZE
+                    )));
+                }
+                CREATE_BASE_GAS + CREATE_GAS_PER_BYTE * bytecode_size
+            },
+        }))
+    }
+}
+
+impl std::str::FromStr for GasPreset {
+    type Err = crate::error::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        GasPreset::ALL.into_iter()
+            .find(|preset| preset.name() == s)
+            .ok_or_else(|| InvalidParam(format!("gas preset {}", s)))
+    }
+}
+
+/// Call to estimate with `eth_estimateGas`
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct GasCall {
+    #[schema(value_type = Option<String>)]
+    pub from: Option<Address>,
+    #[schema(value_type = Option<String>)]
+    pub to: Option<Address>,
+    #[schema(value_type = Option<String>)]
+    pub data: Option<Bytes>,
+    #[schema(value_type = Option<String>)]
+    pub value: Option<U256>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct GasCost {
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub gas: U256,
+    /// Block whose fee level is used, `None` for the next block
+    pub block_number: Option<u64>,
+    #[serde(serialize_with = "crate::units::wei_per_gas")]
+    #[schema(value_type = String, format = "wei-per-gas")]
+    pub fee_per_gas: U256,
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub wei: U256,
+    pub gwei: f64,
+    pub eth: f64,
+    pub usd: f64,
+}
+
+fn parse_units(wei: U256, units: &str) -> Result<f64> {
+    format_units(wei, units)
+        .map_err(EthConvErr)?
+        .parse::<f64>()
+        .map_err(EthConvStrErr)
+}
+
+/// Expected fee per gas and ETH/USD price: next block (standard tier of the gas
+/// oracle, current price) or a past block (its base fee plus the median priority
+/// fee paid in it, price at that block)
+async fn get_fee_level(provider: Arc<Provider<MeteredHttp>>, block_number: Option<u64>) -> Result<(U256, U256)> {
+    match block_number {
+        None => {
+            let oracle = gas_oracle::get_gas_oracle(
+                provider.clone(),
+                gas_oracle::FEE_HISTORY_BLOCKS,
+                gas_oracle::DEFAULT_PERCENTILES
+            ).await?;
+            let usd_per_eth = eth_explore::get_eth_usd_price(provider).await?;
+            Ok((oracle.standard.expected_fee_per_gas, usd_per_eth))
+        },
+        Some(block_number) => {
+            let block = provider
+                .get_block(block_number)
+                .await
+                .map_err(EthProviderErr)?
+                .ok_or_else(|| NotFound(format!("block {}", block_number)))?;
+            let base_fee = block.base_fee_per_gas.unwrap_or_default();
+            // Fails for blocks out of the node fee history window
+            let history = provider
+                .fee_history(1u64, BlockNumber::Number(block_number.into()), &[50.0])
+                .await
+                .map_err(EthProviderErr)?;
+            let priority_fee = history.reward.first().and_then(|rewards| rewards.first()).copied().unwrap_or_default();
+            let usd_per_eth = eth_explore::get_eth_usd_price_at(provider, block_number, block.timestamp).await?;
+            Ok((base_fee + priority_fee, usd_per_eth))
+        },
+    }
+}
+
+/// Cost of `gas` units at the fee level of `block_number` (or the next block)
+pub async fn get_gas_cost(provider: Arc<Provider<MeteredHttp>>, gas: U256, block_number: Option<u64>) -> Result<GasCost> {
+    let (fee_per_gas, usd_per_eth) = get_fee_level(provider, block_number).await?;
+    let wei = gas * fee_per_gas;
+    Ok(GasCost {
+        gas,
+        block_number,
+        fee_per_gas,
+        wei,
+        gwei: parse_units(wei, "gwei")?,
+        eth: parse_units(wei, "ether")?,
+        usd: eth_explore::usd_value(wei, usd_per_eth)?,
+    })
+}
+
+/// Gas of a call with `eth_estimateGas`
+pub async fn estimate_gas(provider: Arc<Provider<MeteredHttp>>, call: &GasCall) -> Result<U256> {
+    let mut tx = Eip1559TransactionRequest::new();
+    if let Some(from) = call.from {
+        tx = tx.from(from);
+    }
+    if let Some(to) = call.to {
+        tx = tx.to(to);
+    }
+    if let Some(data) = call.data.clone() {
+        tx = tx.data(data);
+    }
+    if let Some(value) = call.value {
+        tx = tx.value(value);
+    }
+    provider.estimate_gas(&tx.into(), None).await.map_err(EthProviderErr)
+}
+
+pub fn print_gas_cost(cost: &GasCost) {
+    println!(
+        r#"
+        Gas cost
+        ---------------
+        {:>14} gas
+        {:>14.2} gwei
+        {:>14.6} ETH
+        {:>14.2} usd
+        "#,
+        cost.gas, cost.gwei, cost.eth, cost.usd
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_round_trip_their_names() {
+        for preset in GasPreset::ALL {
+            assert_eq!(preset.name().parse::<GasPreset>().unwrap(), preset);
+        }
+        assert!("swap".parse::<GasPreset>().is_err());
+    }
+
+    #[test]
+    fn preset_gas() {
+        assert_eq!(GasPreset::EthTransfer.gas(0).unwrap(), U256::from(21_000));
+        assert_eq!(GasPreset::Erc20Transfer.gas(1000).unwrap(), U256::from(65_000));
+        assert_eq!(GasPreset::ContractDeploy.gas(0).unwrap(), U256::from(CREATE_BASE_GAS));
+        assert_eq!(GasPreset::ContractDeploy.gas(100).unwrap(), U256::from(53_000 + 21_600));
+    }
+
+    #[test]
+    fn bytecode_size_is_capped() {
+        let max = GasPreset::ContractDeploy.gas(MAX_CODE_SIZE).unwrap();
+        assert_eq!(max, U256::from(CREATE_BASE_GAS + CREATE_GAS_PER_BYTE * MAX_CODE_SIZE));
+        assert!(GasPreset::ContractDeploy.gas(MAX_CODE_SIZE + 1).is_err());
+        assert!(GasPreset::ContractDeploy.gas(u64::MAX).is_err());
+        // Other presets ignore the size
+        assert!(GasPreset::NftMint.gas(u64::MAX).is_ok());
+    }
+}
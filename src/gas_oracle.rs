@@ -0,0 +1,193 @@
+use crate::eth_explore;
+use crate::metrics::MeteredHttp;
+use crate::{error::Error::*, Result};
+
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+use utoipa::ToSchema;
+
+use ethers::providers::{Middleware, Provider};
+use ethers::{
+    core::utils::format_units,
+    types::{U256, BlockNumber}
+};
+
+/// Number of blocks read with `eth_feeHistory`
+pub const FEE_HISTORY_BLOCKS: u64 = 20;
+/// Most blocks served by one `eth_feeHistory` call (Geth limit)
+pub const MAX_FEE_HISTORY_BLOCKS: u64 = 1024;
+/// Reward percentiles of the slow, standard and fast tiers
+pub const DEFAULT_PERCENTILES: [f64; 3] = [10.0, 50.0, 90.0];
+const TRANSFER_GAS: u64 = 21000;
+
+/// Suggested EIP-1559 fees for one tier
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct GasSuggestion {
+    pub percentile: f64,
+    #[serde(serialize_with = "crate::units::wei_per_gas")]
+    #[schema(value_type = String, format = "wei-per-gas")]
+    pub max_priority_fee_per_gas: U256,
+    /// Covers the next base fee doubling (`2 * base fee + priority fee`)
+    #[serde(serialize_with = "crate::units::wei_per_gas")]
+    #[schema(value_type = String, format = "wei-per-gas")]
+    pub max_fee_per_gas: U256,
+    /// Expected price per gas unit (`base fee + priority fee`)
+    #[serde(serialize_with = "crate::units::wei_per_gas")]
+    #[schema(value_type = String, format = "wei-per-gas")]
+    pub expected_fee_per_gas: U256,
+    pub expected_gwei: f64,
+    pub usd_per_gas: f64,
+    /// Cost of a 21000 gas ETH transfer
+    pub usd_transfer: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct GasOracle {
+    pub block_number: u64,
+    /// Base fee of the next block
+    #[serde(serialize_with = "crate::units::wei_per_gas")]
+    #[schema(value_type = String, format = "wei-per-gas")]
+    pub base_fee_per_gas: U256,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub usd_per_eth: U256,
+    pub slow: GasSuggestion,
+    pub standard: GasSuggestion,
+    pub fast: GasSuggestion,
+}
+
+fn to_gwei(wei: U256) -> Result<f64> {
+    format_units(wei, "gwei")
+        .map_err(EthConvErr)?
+        .parse::<f64>()
+        .map_err(EthConvStrErr)
+}
+
+// Median of the non-empty rewards, empty blocks report zeroes
+fn median(mut values: Vec<U256>) -> U256 {
+    values.retain(|value| !value.is_zero());
+    if values.is_empty() {
+        return U256::zero();
+    }
+    values.sort();
+    values[values.len() / 2]
+}
+
+fn suggestion(percentile: f64, base_fee: U256, priority_fee: U256, usd_per_eth: U256) -> Result<GasSuggestion> {
+    let expected_fee_per_gas = base_fee + priority_fee;
+    let usd_per_gas = eth_explore::usd_value(expected_fee_per_gas, usd_per_eth)?;
+    Ok(GasSuggestion {
+        percentile,
+        max_priority_fee_per_gas: priority_fee,
+        max_fee_per_gas: base_fee * 2 + priority_fee,
+        expected_fee_per_gas,
+        expected_gwei: to_gwei(expected_fee_per_gas)?,
+        usd_per_gas,
+        usd_transfer: usd_per_gas * TRANSFER_GAS as f64,
+    })
+}
+
+/// Slow, standard and fast percentiles from a `10,50,90` list
+pub fn parse_percentiles(percentiles: &str) -> Result<[f64; 3]> {
+    percentiles
+        .split(',')
+        .map(|percentile| percentile.trim().parse::<f64>())
+        .collect::<std::result::Result<Vec<f64>, _>>()
+        .ok()
+        .and_then(|percentiles| <[f64; 3]>::try_from(percentiles).ok())
+        .ok_or_else(|| InvalidParam(format!("percentiles {}", percentiles)))
+}
+
+fn check_params(blocks: u64, percentiles: &[f64; 3]) -> Result<()> {
+    if !(1..=MAX_FEE_HISTORY_BLOCKS).contains(&blocks) {
+        return Err(InvalidParam(format!("blocks {} (1 to {})", blocks, MAX_FEE_HISTORY_BLOCKS)));
+    }
+    if percentiles.iter().any(|percentile| !(0.0..=100.0).contains(percentile))
+        || percentiles.windows(2).any(|pair| pair[0] > pair[1]) {
+        return Err(InvalidParam(format!("percentiles {:?}", percentiles)));
+    }
+    Ok(())
+}
+
+/// Slow / standard / fast fee suggestions from the priority fees paid in the
+/// last `blocks` blocks at the given reward `percentiles`
+pub async fn get_gas_oracle(
+    provider: Arc<Provider<MeteredHttp>>,
+    blocks: u64,
+    percentiles: [f64; 3]
+) -> Result<GasOracle> {
+    check_params(blocks, &percentiles)?;
+
+    let history = provider
+        .fee_history(blocks, BlockNumber::Latest, &percentiles)
+        .await
+        .map_err(EthProviderErr)?;
+
+    // `base_fee_per_gas` has one more entry: the base fee of the next block
+    let base_fee = history.base_fee_per_gas.last().copied().unwrap_or_default();
+    let block_number = (history.oldest_block + history.reward.len()).low_u64().saturating_sub(1);
+    let usd_per_eth = eth_explore::get_eth_usd_price(provider.clone()).await?;
+
+    let priority_fee = |tier: usize| median(
+        history.reward.iter()
+            .filter_map(|rewards| rewards.get(tier).copied())
+            .collect()
+    );
+
+    Ok(GasOracle {
+        block_number,
+        base_fee_per_gas: base_fee,
+        usd_per_eth,
+        slow: suggestion(percentiles[0], base_fee, priority_fee(0), usd_per_eth)?,
+        standard: suggestion(percentiles[1], base_fee, priority_fee(1), usd_per_eth)?,
+        fast: suggestion(percentiles[2], base_fee, priority_fee(2), usd_per_eth)?,
+    })
+}
+
+pub fn print_gas_oracle(oracle: &GasOracle) {
+    println!("Fee history suggestions (next block {})", oracle.block_number + 1);
+    println!("---------------");
+    for (tier, suggestion) in [
+        ("slow", &oracle.slow),
+        ("standard", &oracle.standard),
+        ("fast", &oracle.fast),
+    ] {
+        println!(
+            "{:>8} (p{:<4}) {:>10.2} gwei  max fee {:>10.2} gwei  {:>5.2} usd (for 21000 unit)",
+            tier,
+            suggestion.percentile,
+            suggestion.expected_gwei,
+            to_gwei(suggestion.max_fee_per_gas).unwrap_or_default(),
+            suggestion.usd_transfer,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_are_three_numbers() {
+        assert_eq!(parse_percentiles("10, 50,90").unwrap(), [10.0, 50.0, 90.0]);
+        assert!(parse_percentiles("10,50").is_err());
+        assert!(parse_percentiles("10,fast,90").is_err());
+    }
+
+    #[test]
+    fn params_are_bounded() {
+        assert!(check_params(FEE_HISTORY_BLOCKS, &DEFAULT_PERCENTILES).is_ok());
+        assert!(check_params(MAX_FEE_HISTORY_BLOCKS, &DEFAULT_PERCENTILES).is_ok());
+        assert!(check_params(0, &DEFAULT_PERCENTILES).is_err());
+        assert!(check_params(MAX_FEE_HISTORY_BLOCKS + 1, &DEFAULT_PERCENTILES).is_err());
+        assert!(check_params(20, &[90.0, 50.0, 10.0]).is_err());
+        assert!(check_params(20, &[10.0, 50.0, 101.0]).is_err());
+    }
+
+    #[test]
+    fn median_ignores_empty_blocks() {
+        let values = [0u64, 3, 1, 0, 2].iter().map(|value| U256::from(*value)).collect();
+        assert_eq!(median(values), U256::from(2));
+        assert_eq!(median(vec![U256::zero(); 4]), U256::zero());
+    }
+}
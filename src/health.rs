@@ -0,0 +1,128 @@
+use crate::config;
+use crate::db::Db;
+use crate::metrics::MeteredHttp;
+use crate::Result;
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+use serde::{Serialize, Deserialize};
+use utoipa::ToSchema;
+
+use ethers::providers::{Middleware, Provider};
+
+// Stored head may be this many blocks behind the chain head (`MAX_SYNC_LAG`)
+const MAX_SYNC_LAG: u64 = 64;
+// Each check gives up after
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Fail,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub latency_ms: f64,
+    /// Value checked or error
+    pub detail: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct Readiness {
+    pub status: CheckStatus,
+    pub checks: Vec<Check>,
+}
+
+/// Readiness threshold from the `MAX_SYNC_LAG` environment variable
+pub fn max_sync_lag() -> Result<u64> {
+    config::var("MAX_SYNC_LAG", MAX_SYNC_LAG)
+}
+
+fn check_sync_lag(chain_head: u64, sync_head: u64, max_sync_lag: u64) -> std::result::Result<String, String> {
+    let lag = chain_head.saturating_sub(sync_head);
+    let detail = format!("{} blocks behind (max {})", lag, max_sync_lag);
+    if lag > max_sync_lag {
+        return Err(detail);
+    }
+    Ok(detail)
+}
+
+// Run `check` with a timeout, `Ok(detail)` or `Err(error)`
+async fn timed<F>(name: &str, check: F) -> Check
+where
+    F: Future<Output = std::result::Result<String, String>>,
+{
+    let start = Instant::now();
+    let result = match tokio::time::timeout(CHECK_TIMEOUT, check).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("timed out after {}s", CHECK_TIMEOUT.as_secs())),
+    };
+    let (status, detail) = match result {
+        Ok(detail) => (CheckStatus::Ok, detail),
+        Err(detail) => (CheckStatus::Fail, detail),
+    };
+    Check {
+        name: name.to_string(),
+        status,
+        latency_ms: start.elapsed().as_secs_f64() * 1000.0,
+        detail,
+    }
+}
+
+/// Provider, database and sync lag checks
+pub async fn get_readiness(provider: &Provider<MeteredHttp>, db: &Db, max_sync_lag: u64) -> Readiness {
+    let mut chain_head: Option<u64> = None;
+    let provider_check = timed("provider", async {
+        let block_number = provider.get_block_number().await.map_err(|err| err.to_string())?;
+        chain_head = Some(block_number.as_u64());
+        Ok(format!("block {}", block_number))
+    }).await;
+
+    let db_check = timed("database", async {
+        db.ping().await.map_err(|err| err.to_string())?;
+        Ok("ping".to_string())
+    }).await;
+
+    let sync_check = timed("sync_lag", async {
+        let chain_head = chain_head.ok_or_else(|| "chain head unknown".to_string())?;
+        let sync_head = db.fetch_latest_block_number()
+            .await
+            .map_err(|err| err.to_string())?
+            .ok_or_else(|| "no stored block".to_string())?;
+        check_sync_lag(chain_head, sync_head, max_sync_lag)
+    }).await;
+
+    let checks = vec![provider_check, db_check, sync_check];
+    let status = if checks.iter().all(|check| check.status == CheckStatus::Ok) {
+        CheckStatus::Ok
+    } else {
+        CheckStatus::Fail
+    };
+    Readiness { status, checks }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sync_lag_threshold() {
+        assert_eq!(check_sync_lag(100, 100, 64), Ok("0 blocks behind (max 64)".to_string()));
+        assert!(check_sync_lag(164, 100, 64).is_ok());
+        assert_eq!(check_sync_lag(165, 100, 64), Err("65 blocks behind (max 64)".to_string()));
+        // Stored head ahead of a lagging node
+        assert!(check_sync_lag(90, 100, 0).is_ok());
+    }
+
+    #[tokio::test]
+    async fn timed_checks_report_their_status() {
+        let ok = timed("ok", async { Ok("fine".to_string()) }).await;
+        assert_eq!((ok.name.as_str(), ok.status, ok.detail.as_str()), ("ok", CheckStatus::Ok, "fine"));
+        let fail = timed("fail", async { Err("down".to_string()) }).await;
+        assert_eq!((fail.status, fail.detail.as_str()), (CheckStatus::Fail, "down"));
+    }
+}
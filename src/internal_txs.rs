@@ -0,0 +1,393 @@
+use crate::config;
+use crate::db::Db;
+use crate::metrics::MeteredHttp;
+use crate::{error::Error::*, Result};
+
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+use serde::{Serialize, Deserialize};
+use utoipa::ToSchema;
+use serde_json::json;
+
+use ethers::providers::{Middleware, Provider};
+use ethers::types::{
+    U64, U256, H256, Address, BlockNumber,
+    Action, Res, CallType, Trace,
+};
+
+/// Which tracing API of the node is used, set with `TRACE_MODE=geth|parity`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceMode {
+    /// `debug_traceBlockByNumber` with the `callTracer` (Geth, Erigon, Reth)
+    Geth,
+    /// `trace_block` (OpenEthereum, Nethermind, Erigon)
+    Parity,
+}
+
+impl FromStr for TraceMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "geth" | "debug" => Ok(TraceMode::Geth),
+            "parity" | "trace" => Ok(TraceMode::Parity),
+            _ => Err(format!("unknown trace mode {}", s)),
+        }
+    }
+}
+
+impl TraceMode {
+    pub fn from_env() -> Result<TraceMode> {
+        config::var("TRACE_MODE", TraceMode::Geth)
+    }
+}
+
+// DELEGATECALL and CALLCODE run the callee code on the caller's balance, the
+// value they report is the parent's `msg.value`: no ETH changes hands
+fn moves_value(call_type: &str) -> bool {
+    matches!(call_type, "call" | "create" | "create2" | "selfdestruct")
+}
+
+/// A value transfer made by a contract during a transaction
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct InternalTransfer {
+    pub block_number: u64,
+    #[schema(value_type = Option<String>)]
+    pub transaction_hash: Option<H256>,
+    /// Position of the call in the flattened call tree of the transaction
+    pub trace_index: u64,
+    /// 1 for a call made by the transaction target, 2 for a call made by that call...
+    pub depth: u64,
+    pub call_type: String,
+    #[schema(value_type = String)]
+    pub from: Address,
+    #[schema(value_type = Option<String>)]
+    pub to: Option<Address>,
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub value: U256,
+    pub failed: bool,
+}
+
+// callTracer output
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CallFrame {
+    #[serde(rename = "type")]
+    call_type: String,
+    from: Address,
+    to: Option<Address>,
+    value: Option<U256>,
+    error: Option<String>,
+    #[serde(default)]
+    calls: Vec<CallFrame>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct TxTrace {
+    #[serde(rename = "txHash")]
+    tx_hash: Option<H256>,
+    result: CallFrame,
+}
+
+// Depth-first walk of the call tree, the root frame is the transaction itself
+fn flatten_call_frame(
+    frame: &CallFrame,
+    depth: u64,
+    parent_failed: bool,
+    block_number: u64,
+    transaction_hash: Option<H256>,
+    transfers: &mut Vec<InternalTransfer>,
+    trace_index: &mut u64,
+) {
+    let failed = parent_failed || frame.error.is_some();
+    let value = frame.value.unwrap_or_default();
+    let call_type = frame.call_type.to_lowercase();
+    if depth > 0 && !value.is_zero() && moves_value(&call_type) {
+        transfers.push(InternalTransfer {
+            block_number,
+            transaction_hash,
+            trace_index: *trace_index,
+            depth,
+            call_type,
+            from: frame.from,
+            to: frame.to,
+            value,
+            failed,
+        });
+    }
+    *trace_index += 1;
+    for call in frame.calls.iter() {
+        flatten_call_frame(call, depth + 1, failed, block_number, transaction_hash, transfers, trace_index);
+    }
+}
+
+async fn trace_block_geth(
+    provider: Arc<Provider<MeteredHttp>>,
+    block_number: u64,
+    transaction_hashes: &[H256]
+) -> Result<Vec<InternalTransfer>> {
+    let params = json!([
+        format!("0x{:x}", U64::from(block_number)),
+        { "tracer": "callTracer" }
+    ]);
+    let traces: Vec<TxTrace> = provider
+        .request("debug_traceBlockByNumber", params)
+        .await
+        .map_err(EthProviderErr)?;
+    Ok(flatten_tx_traces(&traces, block_number, transaction_hashes))
+}
+
+fn flatten_tx_traces(traces: &[TxTrace], block_number: u64, transaction_hashes: &[H256]) -> Vec<InternalTransfer> {
+    let mut transfers: Vec<InternalTransfer> = vec![];
+    for (i, trace) in traces.iter().enumerate() {
+        let transaction_hash = trace.tx_hash.or_else(|| transaction_hashes.get(i).copied());
+        let mut trace_index = 0;
+        flatten_call_frame(&trace.result, 0, false, block_number, transaction_hash, &mut transfers, &mut trace_index);
+    }
+    transfers
+}
+
+async fn trace_block_parity(provider: Arc<Provider<MeteredHttp>>, block_number: u64) -> Result<Vec<InternalTransfer>> {
+    let traces: Vec<Trace> = provider
+        .trace_block(BlockNumber::Number(block_number.into()))
+        .await
+        .map_err(EthProviderErr)?;
+    Ok(flatten_traces(&traces, block_number))
+}
+
+fn flatten_traces(traces: &[Trace], block_number: u64) -> Vec<InternalTransfer> {
+    let mut transfers: Vec<InternalTransfer> = vec![];
+    let mut trace_index = 0;
+    let mut last_transaction: Option<H256> = None;
+    // Trace addresses of the failed frames of the transaction: the calls under
+    // a reverted frame carry no error of their own
+    let mut failed_frames: Vec<&[usize]> = vec![];
+    for trace in traces.iter() {
+        if trace.transaction_hash != last_transaction {
+            last_transaction = trace.transaction_hash;
+            trace_index = 0;
+            failed_frames.clear();
+        }
+        let depth = trace.trace_address.len() as u64;
+        if trace.error.is_some() {
+            failed_frames.push(&trace.trace_address);
+        }
+        let failed = failed_frames.iter().any(|frame| trace.trace_address.starts_with(frame));
+        let transfer = match &trace.action {
+            Action::Call(call) => Some((
+                match call.call_type {
+                    CallType::CallCode => "callcode",
+                    CallType::DelegateCall => "delegatecall",
+                    CallType::StaticCall => "staticcall",
+                    _ => "call",
+                },
+                call.from,
+                Some(call.to),
+                call.value,
+            )),
+            Action::Create(create) => {
+                let to = match &trace.result {
+                    Some(Res::Create(result)) => Some(result.address),
+                    _ => None,
+                };
+                Some(("create", create.from, to, create.value))
+            },
+            Action::Suicide(suicide) => {
+                Some(("selfdestruct", suicide.address, Some(suicide.refund_address), suicide.balance))
+            },
+            // Block rewards are not internal transactions
+            Action::Reward(_) => None,
+        };
+        if let Some((call_type, from, to, value)) = transfer {
+            if depth > 0 && !value.is_zero() && moves_value(call_type) {
+                transfers.push(InternalTransfer {
+                    block_number,
+                    transaction_hash: trace.transaction_hash,
+                    trace_index,
+                    depth,
+                    call_type: call_type.to_string(),
+                    from,
+                    to,
+                    value,
+                    failed,
+                });
+            }
+        }
+        trace_index += 1;
+    }
+    transfers
+}
+
+/// Trace one block and flatten its call trees into internal transfers
+pub async fn trace_block(
+    provider: Arc<Provider<MeteredHttp>>,
+    mode: TraceMode,
+    block_number: u64,
+    transaction_hashes: &[H256]
+) -> Result<Vec<InternalTransfer>> {
+    match mode {
+        TraceMode::Geth => trace_block_geth(provider, block_number, transaction_hashes).await,
+        TraceMode::Parity => trace_block_parity(provider, block_number).await,
+    }
+}
+
+/// Optional stage: trace the blocks already downloaded in the database
+/// and store their internal transfers (needs a node with debug or trace API)
+pub async fn trace_blocks_history(
+    provider: Arc<Provider<MeteredHttp>>,
+    db: Db,
+    from: u64
+) -> Result<()> {
+    let mode = TraceMode::from_env()?;
+    let to = provider.get_block_number().await.map_err(EthProviderErr)?.as_u64();
+
+    // Start time
+    let start = Instant::now();
+    let mut transfer_number: usize = 0;
+
+    for block_number in from..=to {
+        if let Some(block) = db.fetch_ethblocks(U64::from(block_number)).await? {
+            let transaction_hashes: Vec<H256> = block.transactions.iter().map(|tx| tx.hash).collect();
+            let transfers = trace_block(provider.clone(), mode, block_number, &transaction_hashes).await?;
+            transfer_number += transfers.len();
+
+            // Replace the previous tracing of the block if any
+            db.delete_internal_transfers(block_number).await?;
+            db.create_internal_transfers(&transfers).await?;
+
+            print!("{}", (8u8 as char));
+            print!(
+                "Tracing... Block {} ({:.2} Blocks/s):\r",
+                block_number,
+                ((block_number - from) as f64) / start.elapsed().as_secs_f64()
+            );
+        }
+    }
+    println!("{} internal transfers found", transfer_number);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn call_tracer_skips_delegated_value() {
+        // Proxy forwards 1 ETH of msg.value to its implementation, which pays 0x03 and
+        // a reverted call to 0x04
+        let traces: Vec<TxTrace> = serde_json::from_value(json!([{
+            "txHash": H256::repeat_byte(0xaa),
+            "result": {
+                "type": "CALL",
+                "from": Address::repeat_byte(0x01),
+                "to": Address::repeat_byte(0x02),
+                "value": "0xde0b6b3a7640000",
+                "calls": [{
+                    "type": "DELEGATECALL",
+                    "from": Address::repeat_byte(0x02),
+                    "to": Address::repeat_byte(0x0f),
+                    "value": "0xde0b6b3a7640000",
+                    "calls": [
+                        { "type": "CALL", "from": Address::repeat_byte(0x02), "to": Address::repeat_byte(0x03), "value": "0x10" },
+                        { "type": "CALL", "from": Address::repeat_byte(0x02), "to": Address::repeat_byte(0x04), "value": "0x20", "error": "execution reverted" },
+                        { "type": "STATICCALL", "from": Address::repeat_byte(0x02), "to": Address::repeat_byte(0x05) }
+                    ]
+                }, {
+                    "type": "CREATE2",
+                    "from": Address::repeat_byte(0x02),
+                    "to": Address::repeat_byte(0x06),
+                    "value": "0x30"
+                }]
+            }
+        }])).unwrap();
+
+        let transfers = flatten_tx_traces(&traces, 100, &[]);
+        let summary: Vec<(u64, u64, &str, bool)> = transfers.iter()
+            .map(|transfer| (transfer.trace_index, transfer.depth, transfer.call_type.as_str(), transfer.failed))
+            .collect();
+        assert_eq!(summary, vec![(2, 2, "call", false), (3, 2, "call", true), (5, 1, "create2", false)]);
+        let values: Vec<(Option<Address>, U256)> = transfers.iter().map(|transfer| (transfer.to, transfer.value)).collect();
+        assert_eq!(values, vec![
+            (Some(Address::repeat_byte(0x03)), U256::from(0x10)),
+            (Some(Address::repeat_byte(0x04)), U256::from(0x20)),
+            (Some(Address::repeat_byte(0x06)), U256::from(0x30)),
+        ]);
+        assert!(transfers.iter().all(|transfer| transfer.transaction_hash == Some(H256::repeat_byte(0xaa))));
+    }
+
+    // `trace_block` call frame of transaction 0xaa..., failed when `error` is set
+    fn parity_call(call_type: &str, trace_address: Vec<u64>, to: u8, value: &str, error: Option<&str>) -> serde_json::Value {
+        let mut trace = json!({
+            "action": {
+                "callType": call_type,
+                "from": Address::repeat_byte(0x02),
+                "to": Address::repeat_byte(to),
+                "gas": "0x0",
+                "input": "0x",
+                "value": value
+            },
+            "result": { "gasUsed": "0x0", "output": "0x" },
+            "subtraces": 0,
+            "traceAddress": trace_address,
+            "transactionHash": H256::repeat_byte(0xaa),
+            "transactionPosition": 0,
+            "blockNumber": 100,
+            "blockHash": H256::zero(),
+            "type": "call"
+        });
+        if let Some(error) = error {
+            trace["result"] = serde_json::Value::Null;
+            trace["error"] = json!(error);
+        }
+        trace
+    }
+
+    #[test]
+    fn trace_block_skips_delegated_value() {
+        let call = |call_type: &str, trace_address: Vec<u64>, to: u8, value: &str| {
+            parity_call(call_type, trace_address, to, value, None)
+        };
+        let traces: Vec<Trace> = serde_json::from_value(json!([
+            call("call", vec![], 0x02, "0x1"),
+            call("delegatecall", vec![0], 0x0f, "0x1"),
+            call("callcode", vec![1], 0x0e, "0x5"),
+            call("call", vec![0, 0], 0x03, "0x10"),
+        ])).unwrap();
+
+        let transfers = flatten_traces(&traces, 100);
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].trace_index, 3);
+        assert_eq!(transfers[0].call_type, "call");
+        assert_eq!(transfers[0].to, Some(Address::repeat_byte(0x03)));
+        assert_eq!(transfers[0].value, U256::from(0x10));
+    }
+
+    #[test]
+    fn trace_block_fails_calls_under_a_reverted_frame() {
+        // Frame [0] reverts: its calls [0, 0] and [0, 0, 0] report no error
+        let traces: Vec<Trace> = serde_json::from_value(json!([
+            parity_call("call", vec![], 0x02, "0x0", None),
+            parity_call("call", vec![0], 0x03, "0x1", Some("Reverted")),
+            parity_call("call", vec![0, 0], 0x04, "0x2", None),
+            parity_call("call", vec![0, 0, 0], 0x05, "0x3", None),
+            parity_call("call", vec![1], 0x06, "0x4", None),
+        ])).unwrap();
+
+        let transfers = flatten_traces(&traces, 100);
+        let failed: Vec<(Option<Address>, bool)> = transfers.iter().map(|transfer| (transfer.to, transfer.failed)).collect();
+        assert_eq!(failed, vec![
+            (Some(Address::repeat_byte(0x03)), true),
+            (Some(Address::repeat_byte(0x04)), true),
+            (Some(Address::repeat_byte(0x05)), true),
+            (Some(Address::repeat_byte(0x06)), false),
+        ]);
+    }
+
+    #[test]
+    fn trace_mode_names() {
+        assert_eq!("geth".parse(), Ok(TraceMode::Geth));
+        assert_eq!("Parity".parse(), Ok(TraceMode::Parity));
+        assert!("erigon".parse::<TraceMode>().is_err());
+    }
+}
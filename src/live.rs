@@ -0,0 +1,190 @@
+use crate::eth_explore::{self, EthBlocks};
+use crate::gas_oracle::{self, GasOracle};
+use crate::units::{self, Units};
+use crate::metrics::MeteredHttp;
+use crate::{error::Error::*, Result};
+
+use std::sync::Arc;
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+use utoipa::{IntoParams, ToSchema};
+
+use actix_web::web::Bytes;
+use futures::stream::{self, Stream, StreamExt};
+use tokio::sync::broadcast;
+use ethers::providers::{Middleware, Provider};
+
+// Heads kept for the slow clients before they skip ahead
+const HEAD_CHANNEL_CAPACITY: usize = 16;
+// Wait before reconnecting the upstream subscription
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// New head, built once and shared by all the clients
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct HeadEvent {
+    pub block: EthBlocks,
+    pub gas: Option<GasOracle>,
+}
+
+pub type HeadSender = broadcast::Sender<Arc<HeadEvent>>;
+
+/// `/stream/heads` query parameters
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HeadQuery {
+    #[serde(default)]
+    pub transactions: bool,
+    #[serde(default)]
+    pub gas: bool,
+    #[serde(default)]
+    pub units: Units,
+    /// Not supported: names are not resolved for the stream, `true` is rejected
+    #[serde(default)]
+    pub ens: bool,
+}
+
+async fn head_event(provider: Arc<Provider<MeteredHttp>>, block_number: u64) -> Result<Option<HeadEvent>> {
+    let Some(block) = provider.get_block_with_txs(block_number).await.map_err(EthProviderErr)? else {
+        return Ok(None);
+    };
+    let block = eth_explore::copy_block_details(provider.clone(), &block).await;
+    let gas = match gas_oracle::get_gas_oracle(provider, gas_oracle::FEE_HISTORY_BLOCKS, gas_oracle::DEFAULT_PERCENTILES).await {
+        Ok(gas) => Some(gas),
+        Err(err) => {
+            eprintln!("Error getting the gas suggestion of block {}: {}", block_number, err);
+            None
+        },
+    };
+    Ok(Some(HeadEvent { block, gas }))
+}
+
+// Single upstream `newHeads` subscription, reconnected when the websocket drops
+async fn follow_heads(provider: Arc<Provider<MeteredHttp>>, sender: HeadSender) {
+    loop {
+        match eth_explore::get_eth_ws_provider().await {
+            Ok(ws_provider) => match ws_provider.subscribe_blocks().await {
+                Ok(mut heads) => {
+                    while let Some(head) = heads.next().await {
+                        let Some(block_number) = head.number else {
+                            continue;
+                        };
+                        // Nobody is listening, skip the downloads
+                        if sender.receiver_count() == 0 {
+                            continue;
+                        }
+                        match head_event(provider.clone(), block_number.as_u64()).await {
+                            Ok(Some(event)) => {
+                                let _ = sender.send(Arc::new(event));
+                            },
+                            Ok(None) => {},
+                            Err(err) => eprintln!("Error building head {}: {}", block_number, err),
+                        }
+                    }
+                    eprintln!("Head subscription closed");
+                },
+                Err(err) => eprintln!("Error subscribing to new heads: {}", err),
+            },
+            Err(err) => eprintln!("Error connecting the websocket provider: {}", err),
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Start the shared head feed, clients subscribe to the returned sender
+pub fn spawn_head_feed(provider: Arc<Provider<MeteredHttp>>) -> HeadSender {
+    let (sender, _) = broadcast::channel(HEAD_CHANNEL_CAPACITY);
+    tokio::spawn(follow_heads(provider, sender.clone()));
+    sender
+}
+
+// Server-Sent Event of a head, with the parts asked by the client
+fn sse_event(event: &HeadEvent, query: &HeadQuery) -> Bytes {
+    let mut block = event.block.clone();
+    if !query.transactions {
+        block.transactions = vec![];
+    }
+    let event = HeadEvent {
+        block,
+        gas: if query.gas { event.gas.clone() } else { None },
+    };
+    let data = units::to_json(&event, query.units);
+    Bytes::from(format!("event: head\ndata: {}\n\n", data))
+}
+
+/// `text/event-stream` body of the heads received by `receiver`
+pub fn sse_stream(
+    receiver: broadcast::Receiver<Arc<HeadEvent>>, 
+    query: HeadQuery
+) -> impl Stream<Item = std::result::Result<Bytes, actix_web::Error>> {
+    stream::unfold((receiver, query), |(mut receiver, query)| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    let bytes = sse_event(&event, &query);
+                    return Some((Ok(bytes), (receiver, query)));
+                },
+                // Too slow, continue with the oldest kept head
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn head(number: u64) -> Arc<HeadEvent> {
+        let block: EthBlocks = serde_json::from_value(json!({
+            "number": format!("{:#x}", number),
+            "timestamp": "0x0",
+            "transaction_number": 1,
+            "transactions": [{
+                "hash": format!("{:?}", ethers::types::H256::repeat_byte(1)),
+                "from": format!("{:?}", ethers::types::Address::zero()),
+                "value": "0x0",
+                "gas": "0x5208",
+            }],
+        })).unwrap();
+        Arc::new(HeadEvent { block, gas: None })
+    }
+
+    fn query(transactions: bool, units: Units) -> HeadQuery {
+        HeadQuery { transactions, gas: false, units, ens: false }
+    }
+
+    fn data(bytes: &Bytes) -> Value {
+        let event = std::str::from_utf8(bytes).unwrap();
+        let data = event.strip_prefix("event: head\ndata: ").unwrap().strip_suffix("\n\n").unwrap();
+        serde_json::from_str(data).unwrap()
+    }
+
+    #[test]
+    fn events_carry_the_asked_parts() {
+        let event = data(&sse_event(&head(100), &query(false, Units::Dec)));
+        assert_eq!(event["block"]["number"], "100");
+        assert_eq!(event["block"]["transactions"], json!([]));
+        assert_eq!(event["gas"], Value::Null);
+
+        let event = data(&sse_event(&head(100), &query(true, Units::Hex)));
+        assert_eq!(event["block"]["number"], "0x64");
+        assert_eq!(event["block"]["transactions"][0]["gas"], "0x5208");
+    }
+
+    #[tokio::test]
+    async fn stream_ends_with_the_feed() {
+        let (sender, receiver) = broadcast::channel(HEAD_CHANNEL_CAPACITY);
+        sender.send(head(1)).unwrap();
+        sender.send(head(2)).unwrap();
+        drop(sender);
+
+        let events: Vec<Bytes> = sse_stream(receiver, query(false, Units::Dec))
+            .map(|event| event.unwrap())
+            .collect()
+            .await;
+        let numbers: Vec<Value> = events.iter().map(|event| data(event)["block"]["number"].clone()).collect();
+        assert_eq!(numbers, vec![json!("1"), json!("2")]);
+    }
+}
@@ -0,0 +1,253 @@
+use crate::db::Db;
+use crate::eth_explore::{self, EthBlocks, EthTransaction};
+use crate::valuation;
+use crate::metrics::MeteredHttp;
+use crate::{error::Error::*, Result};
+
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+use utoipa::{IntoParams, ToSchema};
+
+use ethers::providers::{Middleware, Provider};
+use ethers::{
+    utils::id,
+    types::{U64, U256, H256, Bytes, BlockId, BlockNumber}
+};
+
+// Signatures of common methods, decoded from the function selector
+const KNOWN_METHODS: [&str; 20] = [
+    "transfer(address,uint256)",
+    "approve(address,uint256)",
+    "transferFrom(address,address,uint256)",
+    "safeTransferFrom(address,address,uint256)",
+    "safeTransferFrom(address,address,uint256,bytes)",
+    "safeTransferFrom(address,address,uint256,uint256,bytes)",
+    "setApprovalForAll(address,bool)",
+    "mint(address,uint256)",
+    "deposit()",
+    "withdraw(uint256)",
+    "multicall(bytes[])",
+    "multicall(uint256,bytes[])",
+    "execute(bytes,bytes[],uint256)",
+    "swapExactETHForTokens(uint256,address[],address,uint256)",
+    "swapExactTokensForETH(uint256,uint256,address[],address,uint256)",
+    "swapExactTokensForTokens(uint256,uint256,address[],address,uint256)",
+    "exactInputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))",
+    "exactInput((bytes,address,uint256,uint256,uint256))",
+    "commit(bytes32)",
+    "register(string,address,uint256,bytes32)",
+];
+
+/// Lookup query parameters (`?persist=true` stores what was fetched from the RPC)
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LookupQuery {
+    #[serde(default)]
+    pub persist: bool,
+}
+
+/// Parse a decimal number, hex number, block hash or tag (`latest`, `safe`, `finalized`)
+pub fn parse_block_id(id: &str) -> Result<BlockId> {
+    let id = id.trim().to_lowercase();
+    match id.as_str() {
+        "latest" => return Ok(BlockId::Number(BlockNumber::Latest)),
+        "safe" => return Ok(BlockId::Number(BlockNumber::Safe)),
+        "finalized" => return Ok(BlockId::Number(BlockNumber::Finalized)),
+        _ => {},
+    }
+    let number = match id.strip_prefix("0x") {
+        // 32 bytes is a hash, anything shorter a hex number
+        Some(hex) if hex.len() == 64 => {
+            let hash: H256 = id.parse().map_err(|_| InvalidParam(format!("block hash {}", id)))?;
+            return Ok(BlockId::Hash(hash));
+        },
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => id.parse::<u64>(),
+    };
+    number
+        .map(|number| BlockId::Number(BlockNumber::Number(U64::from(number))))
+        .map_err(|_| InvalidParam(format!("block {}", id)))
+}
+
+/// Block with its transactions from the database, or from the RPC when it is not stored
+pub async fn get_block(provider: Arc<Provider<MeteredHttp>>, db: &Db, id: BlockId, persist: bool) -> Result<EthBlocks> {
+    // Tags move with the chain, resolve them to a number first
+    let id = match id {
+        BlockId::Number(BlockNumber::Number(_)) | BlockId::Hash(_) => id,
+        BlockId::Number(tag) => {
+            let block = provider.get_block(tag).await.map_err(EthProviderErr)?
+                .ok_or_else(|| NotFound(format!("block {}", tag)))?;
+            BlockId::Number(BlockNumber::Number(block.number.unwrap_or_default()))
+        },
+    };
+
+    let stored = match id {
+        BlockId::Hash(hash) => db.fetch_ethblocks_by_hash(hash).await?,
+        BlockId::Number(number) => db.fetch_ethblocks(number.as_number().unwrap_or_default()).await?,
+    };
+    if let Some(blocks) = stored {
+        return Ok(blocks);
+    }
+
+    let block = provider.get_block_with_txs(id).await.map_err(EthProviderErr)?
+        .ok_or_else(|| NotFound(format!("block {:?}", id)))?;
+    let blocks = eth_explore::copy_block_details(provider.clone(), &block).await;
+    if persist && !db.found_one_ethblocks(blocks.number.unwrap_or_default()).await {
+        eth_explore::store_block(provider, db, &blocks).await?;
+    }
+    Ok(blocks)
+}
+
+/// Stored (or downloaded) transaction with its block context, receipt and valuation
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct TransactionDetails {
+    #[serde(flatten)]
+    pub transaction: EthTransaction,
+    /// `None` while pending
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = Option<String>, format = "quantity")]
+    pub block_number: Option<U64>,
+    #[schema(value_type = Option<String>)]
+    pub block_hash: Option<H256>,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = Option<String>, format = "quantity")]
+    pub timestamp: Option<U256>,
+    pub confirmations: u64,
+    /// Receipt status, `None` without a receipt
+    pub success: Option<bool>,
+    /// `gas_used * effective_gas_price`
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = Option<String>, format = "wei")]
+    pub fee: Option<U256>,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = Option<String>, format = "quantity")]
+    pub eth_usd_price: Option<U256>,
+    pub fee_usd: Option<f64>,
+    pub value_usd: Option<f64>,
+    /// Signature of the called method when its selector is known
+    pub method: Option<String>,
+}
+
+/// Signature of a known function selector
+pub fn decode_method(method_id: &Bytes) -> Option<&'static str> {
+    KNOWN_METHODS.into_iter().find(|signature| id(signature)[..] == method_id[..])
+}
+
+// Transaction and its block context, from the database or the RPC
+async fn find_transaction(
+    provider: Arc<Provider<MeteredHttp>>, 
+    db: &Db, 
+    hash: H256
+) -> Result<(EthTransaction, Option<U64>, Option<H256>, Option<U256>, Option<U256>)> {
+    if let Some(block) = db.fetch_ethblocks_by_transaction(hash).await? {
+        let mut transaction = block.transactions.iter()
+            .find(|transaction| transaction.hash == hash)
+            .cloned()
+            .ok_or_else(|| NotFound(format!("transaction {:?}", hash)))?;
+        // Blocks stored before the selector was recorded, plain transfers store an empty one
+        if transaction.method_id.is_none() && transaction.to.is_some() {
+            if let Ok(Some(rpc_transaction)) = provider.get_transaction(hash).await {
+                transaction.method_id = EthTransaction::copy_transaction(&rpc_transaction).method_id;
+            }
+        }
+        return Ok((transaction, block.number, block.hash, Some(block.timestamp), block.eth_usd_price));
+    }
+
+    let rpc_transaction = provider.get_transaction(hash).await.map_err(EthProviderErr)?
+        .ok_or_else(|| NotFound(format!("transaction {:?}", hash)))?;
+    let mut transaction = EthTransaction::copy_transaction(&rpc_transaction);
+    if let Some(receipt) = provider.get_transaction_receipt(hash).await.map_err(EthProviderErr)? {
+        transaction.copy_receipt(&receipt);
+    }
+
+    let Some(block_number) = rpc_transaction.block_number else {
+        return Ok((transaction, None, None, None, None));
+    };
+    let timestamp = provider.get_block(block_number).await.map_err(EthProviderErr)?
+        .map(|block| block.timestamp);
+    // Historical prices need an archive node
+    let eth_usd_price = match timestamp {
+        Some(timestamp) => eth_explore::get_eth_usd_price_at(provider, block_number.as_u64(), timestamp).await.ok(),
+        None => None,
+    };
+    Ok((transaction, Some(block_number), rpc_transaction.block_hash, timestamp, eth_usd_price))
+}
+
+/// Transaction `hash` with its block context, receipt status, fee and decoded method
+pub async fn get_transaction(provider: Arc<Provider<MeteredHttp>>, db: &Db, hash: H256) -> Result<TransactionDetails> {
+    let (transaction, block_number, block_hash, timestamp, eth_usd_price) =
+        find_transaction(provider.clone(), db, hash).await?;
+
+    let confirmations = match block_number {
+        Some(block_number) => {
+            let head = provider.get_block_number().await.map_err(EthProviderErr)?;
+            head.saturating_sub(block_number).as_u64() + 1
+        },
+        None => 0,
+    };
+    let fee = valuation::transaction_fee(&transaction);
+    let fee_usd = match (fee, eth_usd_price) {
+        (Some(fee), Some(price)) => Some(eth_explore::usd_value(fee, price)?),
+        _ => None,
+    };
+    let value_usd = eth_usd_price
+        .map(|price| eth_explore::usd_value(transaction.value, price))
+        .transpose()?;
+
+    Ok(TransactionDetails {
+        block_number,
+        block_hash,
+        timestamp,
+        confirmations,
+        success: transaction.status.map(|status| status.as_u64() == 1),
+        fee,
+        eth_usd_price,
+        fee_usd,
+        value_usd,
+        method: transaction.method_id.as_ref().and_then(decode_method).map(String::from),
+        transaction,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(id: &str) -> Option<u64> {
+        match parse_block_id(id) {
+            Ok(BlockId::Number(BlockNumber::Number(number))) => Some(number.as_u64()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn block_ids() {
+        assert_eq!(number("17000000"), Some(17_000_000));
+        assert_eq!(number("0x1036640"), Some(17_000_000));
+        assert_eq!(number(" 0X10 "), Some(16));
+        assert!(matches!(parse_block_id("Latest"), Ok(BlockId::Number(BlockNumber::Latest))));
+        assert!(matches!(parse_block_id("finalized"), Ok(BlockId::Number(BlockNumber::Finalized))));
+
+        let hash = H256::repeat_byte(0xab);
+        assert!(matches!(parse_block_id(&format!("{:?}", hash)), Ok(BlockId::Hash(parsed)) if parsed == hash));
+    }
+
+    #[test]
+    fn invalid_block_ids() {
+        for id in ["", "pending", "-1", "0x", "0xzz", "18446744073709551616", &format!("0x{}", "g".repeat(64))] {
+            assert!(matches!(parse_block_id(id), Err(InvalidParam(_))), "{}", id);
+        }
+    }
+
+    #[test]
+    fn known_selectors_are_decoded() {
+        // transfer(address,uint256)
+        assert_eq!(decode_method(&Bytes::from(vec![0xa9, 0x05, 0x9c, 0xbb])), Some("transfer(address,uint256)"));
+        // deposit()
+        assert_eq!(decode_method(&Bytes::from(vec![0xd0, 0xe3, 0x0d, 0xb0])), Some("deposit()"));
+        assert_eq!(decode_method(&Bytes::from(vec![0, 0, 0, 0])), None);
+        assert_eq!(decode_method(&Bytes::from(vec![0xa9, 0x05])), None);
+        // Plain transfer, no calldata
+        assert_eq!(decode_method(&Bytes::default()), None);
+    }
+}
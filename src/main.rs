@@ -3,95 +3,1198 @@ use std::process;
 use std::io::Write;
 
 use actix_cors::Cors;
+use actix_web::dev::Service;
 use actix_web::{
     HttpServer, 
     HttpResponse,
     get,
+    post,
+    web,
     App,  
     Responder,
 };
 
 use ethers::providers::{Middleware};
+use ethers::types::{Address, H256, U64, U256};
+use serde::Deserialize;
+use utoipa::{IntoParams, Modify, OpenApi};
+use utoipa::openapi::{RefOr, Schema, schema::Object};
+use utoipa_swagger_ui::SwaggerUi;
 
 use std::{
     error::Error,
     sync::Arc,
 };
 use db::Db;
+use address::{AddressSummary, AddressTransactions, Direction};
+use ens::EnsRecord;
+use error::ErrorBody;
+use eth_explore::{BlockWithdrawal, EthBlocks};
+use feeds::{BlockFeed, TransactionFeed};
+use gas_cost::{GasCall, GasCost};
+use gas_oracle::GasOracle;
+use health::Readiness;
+use internal_txs::InternalTransfer;
+use live::HeadEvent;
+use lookup::TransactionDetails;
+use nft::NftToken;
+use price_feeds::Price;
+use search::SearchResult;
+use stats::{BlockStats, Bucket, BurnStats, SeriesBucket, StoredRange};
+use tokens::TokenMetadata;
+use uncles::MinerRewards;
+use valuation::{BlockUsd, TransactionUsd};
 
+mod config;
 mod db;
 mod error;
 mod eth_explore;
+mod nft;
+mod tokens;
+mod internal_txs;
+mod uncles;
+mod stats;
+mod gas_oracle;
+mod gas_cost;
+mod price_feeds;
+mod valuation;
+mod units;
+mod lookup;
+mod address;
+mod search;
+mod ens;
+mod live;
+mod feeds;
+mod metrics;
+mod health;
 
 type Result<T> = std::result::Result<T, error::Error>;
 
-const FROM_ETH_BLOCK: u64 = 16_976_395; // Let's take a ethereum block
 
 // Actix server side (GET latest Transactions)
+#[utoipa::path(
+    tag = "blocks",
+    params(
+        feeds::FeedQuery,
+        units::UnitsQuery,
+        ens::EnsQuery,
+    ),
+    responses(
+        (status = 200, description = "Most recent transactions, newest first. A page walks back at most 64 blocks and can hold fewer than `count` transactions, continue from `next_cursor`", body = TransactionFeed),
+        (status = 400, description = "Invalid parameter", body = ErrorBody),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+    ),
+)]
 #[get("/transactions")]
-async fn get_latest_transactions() -> impl Responder {
+async fn get_latest_transactions(
+    query: web::Query<feeds::FeedQuery>, 
+    units_query: web::Query<units::UnitsQuery>, 
+    ens_query: web::Query<ens::EnsQuery>
+) -> Result<HttpResponse> {
     // Get Eth Provider
     let eth_provider = Arc::new(eth_explore::get_eth_provider().await);
 
-    // Get transaction from ethers-rs
-    let transactions = eth_explore::get_last_10_eth_transactions(eth_provider)
-        .await
-        .expect("error get last 10 transactions");
-    println!("GET latest transactions : {:?}", transactions);
+    // Connect to db 
+    let db = Db::init().await?;
+
+    // Get transactions from the db or ethers-rs
+    let transactions = feeds::get_latest_transactions(
+        eth_provider.clone(), 
+        &db, 
+        query.count.unwrap_or(feeds::DEFAULT_FEED_COUNT), 
+        query.cursor.as_deref()
+    ).await?;
 
     // response with transactions in JSON
-    HttpResponse::Ok().json(transactions)
+    Ok(ens::json(eth_provider, &db, &transactions, units_query.units, ens_query.ens).await)
+}
+
+// Actix server side (GET liveness, the process answers)
+#[utoipa::path(
+    tag = "health",
+    responses(
+        (status = 200, description = "The process answers"),
+    ),
+)]
+#[get("/healthz")]
+async fn get_healthz() -> impl Responder {
+    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
+}
+
+// Actix server side (GET readiness, RPC, database and sync lag checks)
+#[utoipa::path(
+    tag = "health",
+    responses(
+        (status = 200, description = "Every check passed. The stored head may lag `MAX_SYNC_LAG` blocks (64 by default)", body = Readiness),
+        (status = 503, description = "A check failed", body = Readiness),
+    ),
+)]
+#[get("/readyz")]
+async fn get_readyz() -> Result<HttpResponse> {
+    let max_sync_lag = health::max_sync_lag()?;
+
+    // Get Eth Provider
+    let eth_provider = eth_explore::get_eth_provider().await;
+
+    // Connect to db 
+    let db = Db::init().await?;
+
+    let readiness = health::get_readiness(&eth_provider, &db, max_sync_lag).await;
+
+    // 503 until every check passes
+    Ok(match readiness.status {
+        health::CheckStatus::Ok => HttpResponse::Ok().json(readiness),
+        health::CheckStatus::Fail => HttpResponse::ServiceUnavailable().json(readiness),
+    })
+}
+
+// Actix server side (GET Prometheus metrics)
+#[utoipa::path(
+    tag = "health",
+    responses(
+        (status = 200, description = "Prometheus text format", body = String, content_type = "text/plain"),
+    ),
+)]
+#[get("/metrics")]
+async fn get_metrics() -> Result<HttpResponse> {
+    // Get Eth Provider
+    let eth_provider = eth_explore::get_eth_provider().await;
+
+    // Connect to db 
+    let db = Db::init().await?;
+
+    let metrics = metrics::render(&eth_provider, &db).await;
+
+    // Response in the Prometheus text format
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics))
+}
+
+// Actix server side (Server-Sent Events of the new heads)
+#[utoipa::path(
+    tag = "blocks",
+    params(
+        live::HeadQuery,
+    ),
+    responses(
+        (status = 200, description = "Server-Sent Events named `head`, one per new block", body = HeadEvent, content_type = "text/event-stream"),
+        (status = 400, description = "Invalid parameter", body = ErrorBody),
+    ),
+)]
+#[get("/stream/heads")]
+async fn get_head_stream(heads: web::Data<live::HeadSender>, query: web::Query<live::HeadQuery>) -> Result<HttpResponse> {
+    if query.ens {
+        return Err(error::Error::InvalidParam("ens is not supported on /stream/heads".to_string()));
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(live::sse_stream(heads.subscribe(), query.into_inner())))
 }
 
 // Actix server side (GET latest blocks)
+#[utoipa::path(
+    tag = "blocks",
+    params(
+        feeds::FeedQuery,
+        units::UnitsQuery,
+        ens::EnsQuery,
+    ),
+    responses(
+        (status = 200, description = "Most recent blocks, newest first", body = BlockFeed),
+        (status = 400, description = "Invalid parameter", body = ErrorBody),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+    ),
+)]
 #[get("/blocks")]
-async fn get_latest_blocks() -> impl Responder {
+async fn get_latest_blocks(
+    query: web::Query<feeds::FeedQuery>, 
+    units_query: web::Query<units::UnitsQuery>, 
+    ens_query: web::Query<ens::EnsQuery>
+) -> Result<HttpResponse> {
     // Get Eth Provider
     let eth_provider = Arc::new(eth_explore::get_eth_provider().await);
+
+    // Connect to db 
+    let db = Db::init().await?;
     
-    // Get blocks from ethers-rs
-    let blocks = eth_explore::get_last_10_eth_blocks(eth_provider)
-        .await
-        .expect("error get last 10 blocks");
-    println!("GET latest blocks : {:?}", blocks);
+    // Get blocks from the db or ethers-rs
+    let blocks = feeds::get_latest_blocks(
+        eth_provider.clone(), 
+        &db, 
+        query.count.unwrap_or(feeds::DEFAULT_FEED_COUNT), 
+        query.cursor.as_deref()
+    ).await?;
     
     // Response with blocks in JSON
-    HttpResponse::Ok().json(blocks)
+    Ok(ens::json(eth_provider, &db, &blocks, units_query.units, ens_query.ens).await)
 }
 
-// Actix server side (GET historic data)
-#[get("/historic-data")]
-async fn get_chart_info() -> impl Responder {
+// Actix server side (GET NFT owner and transfer history)
+#[utoipa::path(
+    tag = "tokens",
+    params(
+        ("contract" = String, Path, description = "NFT contract address"),
+        ("token_id" = String, Path, description = "Token id (decimal)"),
+        units::UnitsQuery,
+        ens::EnsQuery,
+    ),
+    responses(
+        (status = 200, description = "Owners and transfer history", body = NftToken),
+        (status = 400, description = "Invalid parameter", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+    ),
+)]
+#[get("/nft/{contract}/{token_id}")]
+async fn get_nft_token(
+    path: web::Path<(String, String)>, 
+    units_query: web::Query<units::UnitsQuery>, 
+    ens_query: web::Query<ens::EnsQuery>
+) -> Result<HttpResponse> {
+    let (contract, token_id) = path.into_inner();
+    let contract: Address = contract
+        .parse()
+        .map_err(|_| error::Error::InvalidParam(format!("contract address {}", contract)))?;
+    let token_id = U256::from_dec_str(&token_id)
+        .map_err(|_| error::Error::InvalidParam(format!("token id {}", token_id)))?;
+
+    // Connect to db 
+    let db = Db::init().await?;
+
+    let token = nft::get_nft_token(&db, contract, token_id).await?;
+    if token.transfers.is_empty() {
+        return Err(error::Error::NotFound(format!("no transfer for token {} of {:?}", token_id, contract)));
+    }
+
+    // Get Eth Provider (ENS names)
+    let eth_provider = Arc::new(eth_explore::get_eth_provider().await);
+
+    // Response with the token in JSON
+    Ok(ens::json(eth_provider, &db, &token, units_query.units, ens_query.ens).await)
+}
+
+// Actix server side (GET token metadata)
+#[utoipa::path(
+    tag = "tokens",
+    params(
+        ("address" = String, Path, description = "ERC-20 contract address"),
+        units::UnitsQuery,
+        ens::EnsQuery,
+    ),
+    responses(
+        (status = 200, description = "Token metadata", body = TokenMetadata),
+        (status = 400, description = "Invalid parameter", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+    ),
+)]
+#[get("/token/{address}")]
+async fn get_token(
+    path: web::Path<String>, 
+    units_query: web::Query<units::UnitsQuery>, 
+    ens_query: web::Query<ens::EnsQuery>
+) -> Result<HttpResponse> {
+    let address = path.into_inner();
+    let address: Address = address
+        .parse()
+        .map_err(|_| error::Error::InvalidParam(format!("token address {}", address)))?;
+
     // Get Eth Provider
     let eth_provider = Arc::new(eth_explore::get_eth_provider().await);
-    
+
     // Connect to db 
-    let db= Db::init().await.expect("db init error");
+    let db = Db::init().await?;
 
-    // Get blocks from ethers-rs
-    let mut blocks_vec = db.fetch_all_ethblocks().await.expect("fetch all eth blocks error");
+    let token = tokens::get_token(eth_provider.clone(), &db, address).await?;
+
+    // Response with the token in JSON
+    Ok(ens::json(eth_provider, &db, &token, units_query.units, ens_query.ens).await)
+}
 
-    if blocks_vec.is_empty() {
+// Actix server side (GET address balance, nonce, code and activity)
+#[utoipa::path(
+    tag = "addresses",
+    params(
+        ("address" = String, Path, description = "Address"),
+        units::UnitsQuery,
+        ens::EnsQuery,
+    ),
+    responses(
+        (status = 200, description = "Balance, nonce, code and activity", body = AddressSummary),
+        (status = 400, description = "Invalid parameter", body = ErrorBody),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+    ),
+)]
+#[get("/address/{address}")]
+async fn get_address(
+    path: web::Path<String>, 
+    units_query: web::Query<units::UnitsQuery>, 
+    ens_query: web::Query<ens::EnsQuery>
+) -> Result<HttpResponse> {
+    let address = path.into_inner();
+    let address: Address = address
+        .parse()
+        .map_err(|_| error::Error::InvalidParam(format!("address {}", address)))?;
 
-        println!("No data in database — Downloading...");
+    // Get Eth Provider
+    let eth_provider = Arc::new(eth_explore::get_eth_provider().await);
+
+    // Connect to db 
+    let db = Db::init().await?;
+
+    let summary = address::get_address_summary(eth_provider.clone(), &db, address).await?;
+
+    // Response with the address summary in JSON
+    Ok(ens::json(eth_provider, &db, &summary, units_query.units, ens_query.ens).await)
+}
 
-        eth_explore::get_transactions_history(eth_provider, db.clone(), FROM_ETH_BLOCK)
-            .await
-            .expect("get transaction history err");
+// Address transactions query parameters (`?page=&page_size=&direction=in|out|self`)
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct AddressTransactionsQuery {
+    #[serde(default)]
+    page: u64,
+    page_size: Option<u64>,
+    #[serde(default)]
+    direction: Direction,
+}
+
+// Actix server side (GET stored transactions of an address)
+#[utoipa::path(
+    tag = "addresses",
+    params(
+        ("address" = String, Path, description = "Address"),
+        AddressTransactionsQuery,
+        units::UnitsQuery,
+        ens::EnsQuery,
+    ),
+    responses(
+        (status = 200, description = "Page of stored transactions, newest first", body = AddressTransactions),
+        (status = 400, description = "Invalid parameter", body = ErrorBody),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+    ),
+)]
+#[get("/address/{address}/transactions")]
+async fn get_address_transactions(
+    path: web::Path<String>, 
+    query: web::Query<AddressTransactionsQuery>, 
+    units_query: web::Query<units::UnitsQuery>, 
+    ens_query: web::Query<ens::EnsQuery>
+) -> Result<HttpResponse> {
+    let address = path.into_inner();
+    let address: Address = address
+        .parse()
+        .map_err(|_| error::Error::InvalidParam(format!("address {}", address)))?;
+
+    // Connect to db 
+    let db = Db::init().await?;
+
+    let transactions = address::get_address_transactions(
+        &db, 
+        address, 
+        query.direction, 
+        query.page, 
+        query.page_size.unwrap_or(address::DEFAULT_PAGE_SIZE)
+    ).await?;
+
+    // Get Eth Provider (ENS names)
+    let eth_provider = Arc::new(eth_explore::get_eth_provider().await);
+
+    // Response with the page of transactions in JSON
+    Ok(ens::json(eth_provider, &db, &transactions, units_query.units, ens_query.ens).await)
+}
+
+// Page query parameters (`?page=&page_size=`)
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct PageQuery {
+    /// From 0
+    #[serde(default)]
+    page: u64,
+    /// 25 by default, at most 100
+    page_size: Option<u64>,
+}
+
+// Actix server side (GET internal transfers of an address)
+#[utoipa::path(
+    tag = "addresses",
+    params(
+        ("address" = String, Path, description = "Address"),
+        PageQuery,
+        units::UnitsQuery,
+        ens::EnsQuery,
+    ),
+    responses(
+        (status = 200, description = "Page of internal transfers from or to the address, newest first", body = [InternalTransfer]),
+        (status = 400, description = "Invalid parameter", body = ErrorBody),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+    ),
+)]
+#[get("/address/{address}/internal")]
+async fn get_address_internal_transfers(
+    path: web::Path<String>, 
+    query: web::Query<PageQuery>, 
+    units_query: web::Query<units::UnitsQuery>, 
+    ens_query: web::Query<ens::EnsQuery>
+) -> Result<HttpResponse> {
+    let address = path.into_inner();
+    let address: Address = address
+        .parse()
+        .map_err(|_| error::Error::InvalidParam(format!("address {}", address)))?;
+
+    // Connect to db 
+    let db = Db::init().await?;
 
-        blocks_vec = db.fetch_all_ethblocks()
-            .await
-            .unwrap();
+    let transfers = address::get_address_internal_transfers(
+        &db, 
+        address, 
+        query.page, 
+        query.page_size.unwrap_or(address::DEFAULT_PAGE_SIZE)
+    ).await?;
 
+    // Get Eth Provider (ENS names)
+    let eth_provider = Arc::new(eth_explore::get_eth_provider().await);
+
+    // Response with internal transfers in JSON
+    Ok(ens::json(eth_provider, &db, &transfers, units_query.units, ens_query.ens).await)
+}
+
+// Actix server side (GET beacon withdrawals of an address)
+#[utoipa::path(
+    tag = "addresses",
+    params(
+        ("address" = String, Path, description = "Address"),
+        units::UnitsQuery,
+        ens::EnsQuery,
+    ),
+    responses(
+        (status = 200, description = "Beacon chain withdrawals to the address", body = [BlockWithdrawal]),
+        (status = 400, description = "Invalid parameter", body = ErrorBody),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+    ),
+)]
+#[get("/address/{address}/withdrawals")]
+async fn get_address_withdrawals(
+    path: web::Path<String>, 
+    units_query: web::Query<units::UnitsQuery>, 
+    ens_query: web::Query<ens::EnsQuery>
+) -> Result<HttpResponse> {
+    let address = path.into_inner();
+    let address: Address = address
+        .parse()
+        .map_err(|_| error::Error::InvalidParam(format!("address {}", address)))?;
+
+    // Connect to db 
+    let db = Db::init().await?;
+
+    let withdrawals = db.fetch_withdrawals_by_address(address).await?;
+
+    // Get Eth Provider (ENS names)
+    let eth_provider = Arc::new(eth_explore::get_eth_provider().await);
+
+    // Response with withdrawals in JSON
+    Ok(ens::json(eth_provider, &db, &withdrawals, units_query.units, ens_query.ens).await)
+}
+
+// Longest block range read by the /stats endpoints
+const MAX_RANGE_BLOCKS: u64 = 100_000;
+
+fn check_range(from: u64, to: u64) -> Result<()> {
+    if from > to || to - from >= MAX_RANGE_BLOCKS {
+        return Err(error::Error::InvalidParam(format!(
+            "range {}..{} (at most {} blocks)", from, to, MAX_RANGE_BLOCKS
+        )));
     }
-    
-    // Response with blocks in JSON
-    HttpResponse::Ok().json(blocks_vec)
+    Ok(())
+}
+
+// Block range query parameters (`?from=&to=`)
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct RangeQuery {
+    from: u64,
+    to: u64,
+}
+
+// Actix server side (GET miner rewards over a block range)
+#[utoipa::path(
+    tag = "stats",
+    params(
+        RangeQuery,
+        units::UnitsQuery,
+        ens::EnsQuery,
+    ),
+    responses(
+        (status = 200, description = "Rewards per miner. Uncles only exist before the merge: download pre-merge ranges with menu option 12", body = [MinerRewards]),
+        (status = 400, description = "Invalid parameter", body = ErrorBody),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+    ),
+)]
+#[get("/stats/miners")]
+async fn get_miner_rewards(
+    query: web::Query<RangeQuery>, 
+    units_query: web::Query<units::UnitsQuery>, 
+    ens_query: web::Query<ens::EnsQuery>
+) -> Result<HttpResponse> {
+    check_range(query.from, query.to)?;
+
+    // Connect to db 
+    let db = Db::init().await?;
+
+    let rewards = uncles::get_miner_rewards(&db, query.from, query.to).await?;
+
+    // Get Eth Provider (ENS names)
+    let eth_provider = Arc::new(eth_explore::get_eth_provider().await);
+
+    // Response with miner rewards in JSON
+    Ok(ens::json(eth_provider, &db, &rewards, units_query.units, ens_query.ens).await)
+}
+
+// Block range and bucket query parameters (`?from=&to=&bucket=`)
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct StatsQuery {
+    from: u64,
+    to: u64,
+    #[serde(default)]
+    bucket: Bucket,
+}
+
+// Actix server side (GET EIP-1559 burn over a block range)
+#[utoipa::path(
+    tag = "stats",
+    params(
+        StatsQuery,
+        units::UnitsQuery,
+    ),
+    responses(
+        (status = 200, description = "Burn and issuance", body = BurnStats),
+        (status = 400, description = "Invalid parameter", body = ErrorBody),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+    ),
+)]
+#[get("/stats/burn")]
+async fn get_burn_stats(query: web::Query<StatsQuery>, units_query: web::Query<units::UnitsQuery>) -> Result<HttpResponse> {
+    check_range(query.from, query.to)?;
+
+    // Connect to db 
+    let db = Db::init().await?;
+
+    let burn = stats::get_burn_stats(&db, query.from, query.to, query.bucket).await?;
+
+    // Response with burn statistics in JSON
+    Ok(units::json(&burn, units_query.units))
+}
+
+// Actix server side (GET transaction and gas statistics over a block range)
+#[utoipa::path(
+    tag = "stats",
+    params(
+        StatsQuery,
+        units::UnitsQuery,
+    ),
+    responses(
+        (status = 200, description = "Transaction and gas series", body = [SeriesBucket]),
+        (status = 400, description = "Invalid parameter", body = ErrorBody),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+    ),
+)]
+#[get("/stats/series")]
+async fn get_series_stats(query: web::Query<StatsQuery>, units_query: web::Query<units::UnitsQuery>) -> Result<HttpResponse> {
+    check_range(query.from, query.to)?;
+
+    // Connect to db 
+    let db = Db::init().await?;
+
+    let series = stats::get_series_stats(&db, query.from, query.to, query.bucket).await?;
+
+    // Response with the series in JSON
+    Ok(units::json(&series, units_query.units))
+}
+
+// Actix server side (GET first and last stored blocks)
+#[utoipa::path(
+    tag = "stats",
+    responses(
+        (status = 200, description = "First and last stored block numbers", body = StoredRange),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+    ),
+)]
+#[get("/stats/range")]
+async fn get_stored_range() -> Result<HttpResponse> {
+    // Connect to db 
+    let db = Db::init().await?;
+
+    let range = db.fetch_block_number_range().await?;
+    let stored = stats::StoredRange {
+        from: range.map(|(from, _)| from),
+        to: range.map(|(_, to)| to),
+    };
+
+    // Response with the stored range in JSON
+    Ok(HttpResponse::Ok().json(stored))
 }
 
+// Actix server side (GET materialized per-block statistics)
+#[utoipa::path(
+    tag = "stats",
+    params(
+        RangeQuery,
+        units::UnitsQuery,
+    ),
+    responses(
+        (status = 200, description = "Materialized per-block statistics", body = [BlockStats]),
+        (status = 400, description = "Invalid parameter", body = ErrorBody),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+    ),
+)]
+#[get("/stats/blocks")]
+async fn get_block_stats(query: web::Query<RangeQuery>, units_query: web::Query<units::UnitsQuery>) -> Result<HttpResponse> {
+    check_range(query.from, query.to)?;
+
+    // Connect to db 
+    let db = Db::init().await?;
+
+    let block_stats = db.fetch_block_stats_range(query.from, query.to).await?;
+
+    // Response with the block statistics in JSON
+    Ok(units::json(&block_stats, units_query.units))
+}
+
+// Gas oracle query parameters (`?blocks=&percentiles=10,50,90`)
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct GasQuery {
+    #[param(minimum = 1, maximum = 1024)]
+    blocks: Option<u64>,
+    percentiles: Option<String>,
+}
+
+// Actix server side (GET gas suggestions)
+#[utoipa::path(
+    tag = "gas",
+    params(
+        GasQuery,
+        units::UnitsQuery,
+    ),
+    responses(
+        (status = 200, description = "Slow, standard and fast suggestions", body = GasOracle),
+        (status = 400, description = "Invalid parameter", body = ErrorBody),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+        (status = 503, description = "Stale or invalid oracle price", body = ErrorBody),
+    ),
+)]
+#[get("/gas")]
+async fn get_gas(query: web::Query<GasQuery>, units_query: web::Query<units::UnitsQuery>) -> Result<HttpResponse> {
+    let blocks = query.blocks.unwrap_or(gas_oracle::FEE_HISTORY_BLOCKS);
+    let percentiles = match &query.percentiles {
+        Some(percentiles) => gas_oracle::parse_percentiles(percentiles)?,
+        None => gas_oracle::DEFAULT_PERCENTILES,
+    };
+
+    // Get Eth Provider
+    let eth_provider = Arc::new(eth_explore::get_eth_provider().await);
+
+    let oracle = gas_oracle::get_gas_oracle(eth_provider, blocks, percentiles).await?;
+
+    // Response with gas suggestions in JSON
+    Ok(units::json(&oracle, units_query.units))
+}
+
+// Gas cost query parameters (`?gas=` or `?preset=&bytecode_size=`, optional `&block=`)
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct GasCostQuery {
+    gas: Option<u64>,
+    preset: Option<String>,
+    #[param(maximum = 24576)]
+    bytecode_size: Option<u64>,
+    block: Option<u64>,
+}
+
+// Actix server side (GET cost of a gas amount or of a preset)
+#[utoipa::path(
+    tag = "gas",
+    params(
+        GasCostQuery,
+        units::UnitsQuery,
+    ),
+    responses(
+        (status = 200, description = "Cost of the gas amount", body = GasCost),
+        (status = 400, description = "Invalid parameter", body = ErrorBody),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+        (status = 503, description = "Stale or invalid oracle price", body = ErrorBody),
+    ),
+)]
+#[get("/gas/cost")]
+async fn get_gas_cost(query: web::Query<GasCostQuery>, units_query: web::Query<units::UnitsQuery>) -> Result<HttpResponse> {
+    let gas = match (query.gas, &query.preset) {
+        (Some(gas), None) => U256::from(gas),
+        (None, Some(preset)) => {
+            let preset: gas_cost::GasPreset = preset.parse()?;
+            if preset == gas_cost::GasPreset::ContractDeploy && query.bytecode_size.is_none() {
+                return Err(error::Error::InvalidParam("contract_deploy needs bytecode_size".to_string()));
+            }
+            preset.gas(query.bytecode_size.unwrap_or_default())?
+        },
+        _ => return Err(error::Error::InvalidParam("expected either gas or preset".to_string())),
+    };
+
+    // Get Eth Provider
+    let eth_provider = Arc::new(eth_explore::get_eth_provider().await);
+
+    let cost = gas_cost::get_gas_cost(eth_provider, gas, query.block).await?;
+
+    // Response with the cost in JSON
+    Ok(units::json(&cost, units_query.units))
+}
+
+// Block query parameter (`?block=`)
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct BlockQuery {
+    block: Option<u64>,
+}
+
+// Actix server side (POST estimate the cost of a call)
+#[utoipa::path(
+    tag = "gas",
+    request_body = GasCall,
+    params(
+        BlockQuery,
+        units::UnitsQuery,
+    ),
+    responses(
+        (status = 200, description = "Cost of the estimated gas", body = GasCost),
+        (status = 400, description = "Invalid parameter", body = ErrorBody),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+        (status = 503, description = "Stale or invalid oracle price", body = ErrorBody),
+    ),
+)]
+#[post("/gas/estimate")]
+async fn estimate_gas_cost(
+    query: web::Query<BlockQuery>, 
+    call: web::Json<gas_cost::GasCall>, 
+    units_query: web::Query<units::UnitsQuery>
+) -> Result<HttpResponse> {
+    // Get Eth Provider
+    let eth_provider = Arc::new(eth_explore::get_eth_provider().await);
+
+    let gas = gas_cost::estimate_gas(eth_provider.clone(), &call).await?;
+    let cost = gas_cost::get_gas_cost(eth_provider, gas, query.block).await?;
+
+    // Response with the cost in JSON
+    Ok(units::json(&cost, units_query.units))
+}
+
+// Actix server side (GET price of a pair, e.g. /price/ETH-EUR)
+#[utoipa::path(
+    tag = "prices",
+    params(
+        ("pair" = String, Path, description = "Pair, e.g. ETH-EUR"),
+        units::UnitsQuery,
+    ),
+    responses(
+        (status = 200, description = "Price chained through the Chainlink feeds", body = Price),
+        (status = 400, description = "Invalid parameter", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+        (status = 503, description = "Stale or invalid oracle price", body = ErrorBody),
+    ),
+)]
+#[get("/price/{pair}")]
+async fn get_price(path: web::Path<String>, units_query: web::Query<units::UnitsQuery>) -> Result<HttpResponse> {
+    let (base, quote) = price_feeds::parse_pair(&path.into_inner())?;
+
+    // Get Eth Provider
+    let eth_provider = Arc::new(eth_explore::get_eth_provider().await);
+
+    let price = price_feeds::get_price(eth_provider, &base, &quote).await?;
+
+    // Response with the price in JSON
+    Ok(units::json(&price, units_query.units))
+}
+
+// Search query parameters (`?q=`)
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SearchQuery {
+    q: String,
+}
+
+// Actix server side (GET block, transaction, address or ENS name matching a query)
+#[utoipa::path(
+    tag = "search",
+    params(
+        SearchQuery,
+        units::UnitsQuery,
+        ens::EnsQuery,
+    ),
+    responses(
+        (status = 200, description = "What the query matched and where to navigate", body = SearchResult),
+        (status = 400, description = "Invalid parameter", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+    ),
+)]
+#[get("/search")]
+async fn get_search(
+    query: web::Query<SearchQuery>, 
+    units_query: web::Query<units::UnitsQuery>, 
+    ens_query: web::Query<ens::EnsQuery>
+) -> Result<HttpResponse> {
+    // Get Eth Provider
+    let eth_provider = Arc::new(eth_explore::get_eth_provider().await);
+
+    // Connect to db 
+    let db = Db::init().await?;
+
+    let result = search::search(eth_provider.clone(), &db, &query.q).await?;
+
+    // Response with the search result in JSON
+    Ok(ens::json(eth_provider, &db, &result, units_query.units, ens_query.ens).await)
+}
+
+// Actix server side (GET address of an ENS name, or primary name of an address)
+#[utoipa::path(
+    tag = "search",
+    params(
+        ("query" = String, Path, description = "ENS name or address"),
+    ),
+    responses(
+        (status = 200, description = "Forward or reverse resolution", body = EnsRecord),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+    ),
+)]
+#[get("/ens/{query}")]
+async fn get_ens(path: web::Path<String>) -> Result<HttpResponse> {
+    let query = path.into_inner();
+
+    // Get Eth Provider
+    let eth_provider = Arc::new(eth_explore::get_eth_provider().await);
+
+    // Connect to db 
+    let db = Db::init().await?;
+
+    let record = match query.parse::<Address>() {
+        Ok(address) => ens::lookup_address(eth_provider, &db, address).await?,
+        Err(_) => ens::resolve_name(eth_provider, &db, &query).await?,
+    };
+
+    // Response with the ENS record in JSON
+    Ok(HttpResponse::Ok().json(record))
+}
+
+// Actix server side (GET block by number, hash or tag)
+#[utoipa::path(
+    tag = "blocks",
+    params(
+        ("id" = String, Path, description = "Decimal number, hex number, hash, latest, safe or finalized"),
+        lookup::LookupQuery,
+        units::UnitsQuery,
+        ens::EnsQuery,
+    ),
+    responses(
+        (status = 200, description = "Block with its transactions", body = EthBlocks),
+        (status = 400, description = "Invalid parameter", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+    ),
+)]
+#[get("/block/{id}")]
+async fn get_block(
+    path: web::Path<String>, 
+    query: web::Query<lookup::LookupQuery>, 
+    units_query: web::Query<units::UnitsQuery>, 
+    ens_query: web::Query<ens::EnsQuery>
+) -> Result<HttpResponse> {
+    let id = lookup::parse_block_id(&path.into_inner())?;
+
+    // Get Eth Provider
+    let eth_provider = Arc::new(eth_explore::get_eth_provider().await);
+
+    // Connect to db 
+    let db = Db::init().await?;
+
+    let block = lookup::get_block(eth_provider.clone(), &db, id, query.persist).await?;
+
+    // Response with the block and its transactions in JSON
+    Ok(ens::json(eth_provider, &db, &block, units_query.units, ens_query.ens).await)
+}
+
+// Actix server side (GET block value and fees in USD at block time)
+#[utoipa::path(
+    tag = "prices",
+    params(
+        ("number" = u64, Path, description = "Block number"),
+        units::UnitsQuery,
+    ),
+    responses(
+        (status = 200, description = "Block totals at the block ETH/USD price", body = BlockUsd),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+    ),
+)]
+#[get("/block/{number}/usd")]
+async fn get_block_usd(path: web::Path<u64>, units_query: web::Query<units::UnitsQuery>) -> Result<HttpResponse> {
+    let block_number = path.into_inner();
+
+    // Connect to db 
+    let db = Db::init().await?;
+
+    let block = db.fetch_ethblocks(U64::from(block_number))
+        .await?
+        .ok_or_else(|| error::Error::NotFound(format!("block {}", block_number)))?;
+    let block_usd = valuation::block_usd(&block)?;
+
+    // Response with the block valuation in JSON
+    Ok(units::json(&block_usd, units_query.units))
+}
+
+// Actix server side (GET transaction with receipt and decoded details)
+#[utoipa::path(
+    tag = "blocks",
+    params(
+        ("hash" = String, Path, description = "Transaction hash"),
+        units::UnitsQuery,
+        ens::EnsQuery,
+    ),
+    responses(
+        (status = 200, description = "Transaction with block context and receipt", body = TransactionDetails),
+        (status = 400, description = "Invalid parameter", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+    ),
+)]
+#[get("/tx/{hash}")]
+async fn get_transaction(
+    path: web::Path<String>, 
+    units_query: web::Query<units::UnitsQuery>, 
+    ens_query: web::Query<ens::EnsQuery>
+) -> Result<HttpResponse> {
+    let hash = path.into_inner();
+    let hash: H256 = hash
+        .parse()
+        .map_err(|_| error::Error::InvalidParam(format!("transaction hash {}", hash)))?;
+
+    // Get Eth Provider
+    let eth_provider = Arc::new(eth_explore::get_eth_provider().await);
+
+    // Connect to db 
+    let db = Db::init().await?;
+
+    let transaction = lookup::get_transaction(eth_provider.clone(), &db, hash).await?;
+
+    // Response with the transaction details in JSON
+    Ok(ens::json(eth_provider, &db, &transaction, units_query.units, ens_query.ens).await)
+}
+
+// Actix server side (GET transaction value and fee in USD at block time)
+#[utoipa::path(
+    tag = "prices",
+    params(
+        ("hash" = String, Path, description = "Transaction hash"),
+        units::UnitsQuery,
+    ),
+    responses(
+        (status = 200, description = "Transaction at the block ETH/USD price", body = TransactionUsd),
+        (status = 400, description = "Invalid parameter", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 500, description = "RPC or database error", body = ErrorBody),
+    ),
+)]
+#[get("/tx/{hash}/usd")]
+async fn get_transaction_usd(path: web::Path<String>, units_query: web::Query<units::UnitsQuery>) -> Result<HttpResponse> {
+    let hash = path.into_inner();
+    let hash: H256 = hash
+        .parse()
+        .map_err(|_| error::Error::InvalidParam(format!("transaction hash {}", hash)))?;
+
+    // Connect to db 
+    let db = Db::init().await?;
+
+    let block = db.fetch_ethblocks_by_transaction(hash)
+        .await?
+        .ok_or_else(|| error::Error::NotFound(format!("transaction {:?}", hash)))?;
+    let transaction = block.transactions.iter()
+        .find(|transaction| transaction.hash == hash)
+        .ok_or_else(|| error::Error::NotFound(format!("transaction {:?}", hash)))?;
+    let transaction_usd = valuation::transaction_usd(&block, transaction)?;
+
+    // Response with the transaction valuation in JSON
+    Ok(units::json(&transaction_usd, units_query.units))
+}
+
+// Registers the API handlers with Actix, and keeps their names to check them
+// against the documented operations of `ApiDoc`
+macro_rules! api_handlers {
+    ($($handler:ident),* $(,)?) => {
+        fn api_routes(cfg: &mut web::ServiceConfig) {
+            $(cfg.service($handler);)*
+        }
+
+        #[cfg(test)]
+        const API_HANDLERS: &[&str] = &[$(stringify!($handler)),*];
+    };
+}
+
+api_handlers!(
+    get_metrics,
+    get_healthz,
+    get_readyz,
+    get_head_stream,
+    get_latest_transactions,
+    get_latest_blocks,
+    get_stored_range,
+    get_nft_token,
+    get_token,
+    get_address,
+    get_address_transactions,
+    get_address_internal_transfers,
+    get_address_withdrawals,
+    get_miner_rewards,
+    get_burn_stats,
+    get_series_stats,
+    get_block_stats,
+    get_gas,
+    get_gas_cost,
+    estimate_gas_cost,
+    get_price,
+    get_block,
+    get_search,
+    get_ens,
+    get_block_usd,
+    get_transaction,
+    get_transaction_usd,
+);
+
+// Objects of a schema, nested ones first
+fn visit_objects(schema: &mut RefOr<Schema>, visit: &mut impl FnMut(&mut Object)) {
+    match schema {
+        RefOr::T(Schema::Object(object)) => {
+            object.properties.values_mut().for_each(|property| visit_objects(property, visit));
+            visit(object);
+        },
+        RefOr::T(Schema::Array(array)) => visit_objects(&mut array.items, visit),
+        RefOr::T(Schema::AllOf(all_of)) => all_of.items.iter_mut().for_each(|item| visit_objects(item, visit)),
+        RefOr::T(Schema::OneOf(one_of)) => one_of.items.iter_mut().for_each(|item| visit_objects(item, visit)),
+        _ => {},
+    }
+}
+
+// Request bodies, not changed by `?units=` and `?ens=`
+const REQUEST_SCHEMAS: [&str; 1] = ["GasCall"];
+
+// Documents the decimal quantities and the `_eth`, `_gwei` and `_ens` fields of
+// the `?units=` and `?ens=` query modes in the response schemas
+struct QueryModes;
+
+impl Modify for QueryModes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let Some(components) = openapi.components.as_mut() else {
+            return;
+        };
+        for (name, schema) in components.schemas.iter_mut() {
+            if REQUEST_SCHEMAS.contains(&name.as_str()) {
+                continue;
+            }
+            visit_objects(schema, &mut |object| {
+                units::document_schema(object);
+                ens::document_schema(object);
+            });
+        }
+    }
+}
+
+// OpenAPI document of the Actix handlers, served with Swagger UI at /swagger-ui/
+// Quantities are hex strings unless `?units=` asks for decimal, gwei or ether
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Ethereum Explorer API"),
+    paths(
+        get_metrics,
+        get_healthz,
+        get_readyz,
+        get_head_stream,
+        get_latest_transactions,
+        get_latest_blocks,
+        get_stored_range,
+        get_nft_token,
+        get_token,
+        get_address,
+        get_address_transactions,
+        get_address_internal_transfers,
+        get_address_withdrawals,
+        get_miner_rewards,
+        get_burn_stats,
+        get_series_stats,
+        get_block_stats,
+        get_gas,
+        get_gas_cost,
+        estimate_gas_cost,
+        get_price,
+        get_block,
+        get_search,
+        get_ens,
+        get_block_usd,
+        get_transaction,
+        get_transaction_usd,
+    ),
+    components(schemas(
+        ErrorBody,
+        Direction,
+        address::AddressActivity,
+        AddressSummary,
+        AddressTransactions,
+        ens::EnsLookup,
+        EnsRecord,
+        eth_explore::EthTransaction,
+        eth_explore::BlockTransaction,
+        eth_explore::EthWithdrawal,
+        BlockWithdrawal,
+        EthBlocks,
+        BlockFeed,
+        TransactionFeed,
+        GasCall,
+        GasCost,
+        gas_oracle::GasSuggestion,
+        GasOracle,
+        health::CheckStatus,
+        health::Check,
+        Readiness,
+        InternalTransfer,
+        HeadEvent,
+        TransactionDetails,
+        nft::NftStandard,
+        nft::NftTransfer,
+        nft::NftHolder,
+        NftToken,
+        Price,
+        search::SearchKind,
+        search::SearchSource,
+        SearchResult,
+        Bucket,
+        stats::TransactionTypeCounts,
+        BlockStats,
+        stats::BurnBucket,
+        BurnStats,
+        SeriesBucket,
+        StoredRange,
+        TokenMetadata,
+        MinerRewards,
+        units::Units,
+        TransactionUsd,
+        BlockUsd,
+    )),
+    modifiers(&QueryModes),
+)]
+struct ApiDoc;
+
 // Menu
 fn menu(choice : &mut String) {
-    println!("");
+    println!();
     println!("Menu");
     println!("1) Gas Price");
     println!("2) Get the latest blocks");
@@ -100,12 +1203,17 @@ fn menu(choice : &mut String) {
     println!("5) Fetch history from MongoDB");
     println!("6) Clear data from MongoDB");
     println!("7) Run the webserver with Actix");
+    println!("8) Trace internal transactions of downloaded blocks (needs debug API, or trace API with TRACE_MODE=parity)");
+    println!("9) Gas cost calculator");
+    println!("10) Backfill ETH/USD price of downloaded blocks (needs archive node)");
+    println!("11) Rebuild block statistics from MongoDB");
+    println!("12) Download a block range to MongoDB (pre-merge ranges for uncles and miner rewards)");
     println!("0) Quit");
     println!("Please enter your choice");
     io_stdout_flush_e();
     choice.clear();
     io_stdin_read_line_e(choice);
-    println!("");//space
+    println!();//space
 }
 
 #[tokio::main]
@@ -139,17 +1247,38 @@ async fn main() -> eyre::Result<(), Box<dyn Error>> {
                 // Gas price (w/ ETH price from Chainlink oracle)
                 let (gwei,usd_per_gas,gas_value) = eth_explore::get_gas_price(eth_provider.clone()).await?;
                 eth_explore::print_gas_value(gwei,usd_per_gas,gas_value);
+
+                // Suggestions from eth_feeHistory
+                println!("Enter the slow, standard and fast reward percentiles (Enter for 10,50,90)");
+                let mut input = String::new();
+                io_stdout_flush_e();
+                io_stdin_read_line_e(&mut input);
+                let percentiles = match input.trim() {
+                    "" => Ok(gas_oracle::DEFAULT_PERCENTILES),
+                    percentiles => gas_oracle::parse_percentiles(percentiles),
+                };
+                match percentiles {
+                    Ok(percentiles) => {
+                        let oracle = gas_oracle::get_gas_oracle(
+                            eth_provider.clone(), 
+                            gas_oracle::FEE_HISTORY_BLOCKS, 
+                            percentiles
+                        ).await?;
+                        gas_oracle::print_gas_oracle(&oracle);
+                    },
+                    Err(err) => println!("{}", err),
+                }
             }, 
             2 => {
                 // Get the 10 latest eth blocks
-                let blocks_vec = eth_explore::get_last_10_eth_blocks(eth_provider.clone()).await?;
-                println!("{:#?}", blocks_vec);
+                let feed = feeds::get_latest_blocks(eth_provider.clone(), &db, feeds::DEFAULT_FEED_COUNT, None).await?;
+                println!("{:#?}", feed.blocks);
 
             },
             3 => {
                 // Get the 10 latest eth transactions
-                let transactions_vec = eth_explore::get_last_10_eth_transactions(eth_provider.clone()).await?;
-                println!("{:#?}", transactions_vec);
+                let feed = feeds::get_latest_transactions(eth_provider.clone(), &db, feeds::DEFAULT_FEED_COUNT, None).await?;
+                println!("{:#?}", feed.transactions);
             }, 
             4 => {
                 // Downloading eth blockchain data
@@ -167,21 +1296,99 @@ async fn main() -> eyre::Result<(), Box<dyn Error>> {
             }
             7 => {
                 println!("Waiting for JS Client... Please open frontend/ethscan.html");
+
+                // Lookups by hash and address
+                db.create_indexes().await?;
                 
+                // One upstream head subscription shared by the clients
+                let heads = live::spawn_head_feed(eth_provider.clone());
+
                 // Actix server
-                HttpServer::new(|| {
+                HttpServer::new(move || {
                     let cors = Cors::permissive();
                     App::new()
                     .wrap(cors)
-                    .service(get_latest_transactions)
-                    .service(get_latest_blocks)
-                    .service(get_chart_info)
+                    // HTTP requests by route and status
+                    .wrap_fn(|req, srv| {
+                        let response = srv.call(req);
+                        async move {
+                            let response = response.await?;
+                            let request = response.request();
+                            let route = request.match_pattern().unwrap_or_else(|| "unmatched".to_string());
+                            metrics::METRICS.http_request(&route, request.method().as_str(), response.status().as_u16());
+                            Ok(response)
+                        }
+                    })
+                    .app_data(web::Data::new(heads.clone()))
+                    .configure(api_routes)
+                    .service(
+                        SwaggerUi::new("/swagger-ui/{_:.*}")
+                            .url("/api-docs/openapi.json", ApiDoc::openapi())
+                    )
                 })
                 .bind(("127.0.0.1", 8080))?
                 .run()
                 .await?;
 
             }
+            8 => {
+                // Internal transfers of the downloaded blocks
+                internal_txs::trace_blocks_history(eth_provider.clone(), db.clone(), from_block_number).await?;
+            }
+            9 => {
+                // Gas amount or preset
+                let presets: Vec<&str> = gas_cost::GasPreset::ALL.iter().map(|preset| preset.name()).collect();
+                println!("Enter a gas amount or a preset ({})", presets.join(", "));
+                let mut input = String::new();
+                io_stdout_flush_e();
+                io_stdin_read_line_e(&mut input);
+
+                let gas = match input.trim().parse::<u64>() {
+                    Ok(gas) => Some(U256::from(gas)),
+                    Err(_) => match input.trim().parse::<gas_cost::GasPreset>() {
+                        Ok(gas_cost::GasPreset::ContractDeploy) => {
+                            println!("Enter the bytecode size (bytes)");
+                            let mut size = String::new();
+                            io_stdout_flush_e();
+                            io_stdin_read_line_e(&mut size);
+                            size.trim().parse::<u64>().ok().and_then(|size| gas_cost::GasPreset::ContractDeploy.gas(size).ok())
+                        },
+                        Ok(preset) => preset.gas(0).ok(),
+                        Err(_) => None,
+                    },
+                };
+                match gas {
+                    Some(gas) => {
+                        let cost = gas_cost::get_gas_cost(eth_provider.clone(), gas, None).await?;
+                        gas_cost::print_gas_cost(&cost);
+                    },
+                    None => println!("invalid gas amount, preset or bytecode size (at most {} bytes)", gas_cost::MAX_CODE_SIZE),
+                }
+            }
+            10 => {
+                // Chainlink price at each downloaded block
+                valuation::backfill_block_prices(eth_provider.clone(), db.clone(), from_block_number).await?;
+            }
+            11 => {
+                // block_stats documents of the downloaded blocks
+                let block_number = stats::rebuild_block_stats(&db).await?;
+                println!("{} block statistics rebuilt", block_number);
+            }
+            12 => {
+                // Any range, e.g. before the merge (option 4 downloads the last 500 blocks)
+                println!("Enter the first and the last block numbers (e.g. 15000000 15000100)");
+                let mut input = String::new();
+                io_stdout_flush_e();
+                io_stdin_read_line_e(&mut input);
+
+                let range: Vec<u64> = input.split_whitespace().filter_map(|n| n.parse().ok()).collect();
+                match range.as_slice() {
+                    [from, to] if from <= to => {
+                        eth_explore::download_blocks(eth_provider.clone(), db.clone(), *from, *to).await?;
+                    },
+                    _ => println!("invalid block range"),
+                }
+            }
             _ => {
                 println!("invalid choice, please try again");
             }
@@ -211,3 +1418,59 @@ fn io_stdin_read_line_e(buf: &mut String){
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{body, dev::ServiceRequest, test as actix_test};
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn registered_handlers_are_documented() {
+        let operations: BTreeSet<String> = ApiDoc::openapi().paths.paths.values()
+            .flat_map(|item| item.operations.values())
+            .filter_map(|operation| operation.operation_id.clone())
+            .collect();
+        let handlers: BTreeSet<String> = API_HANDLERS.iter().map(|handler| handler.to_string()).collect();
+        assert_eq!(handlers, operations);
+    }
+
+    #[actix_web::test]
+    async fn documented_paths_are_routed() {
+        // Answers with the matched route instead of calling the handler
+        let app = actix_test::init_service(
+            App::new()
+                .wrap_fn(|req: ServiceRequest, _| {
+                    let route = req.match_pattern().unwrap_or_default();
+                    async move { Ok(req.into_response(HttpResponse::Ok().body(route))) }
+                })
+                .configure(api_routes)
+        ).await;
+
+        for path in ApiDoc::openapi().paths.paths.keys() {
+            // `/block/{id}` is requested as `/block/id`
+            let uri = path.replace(['{', '}'], "");
+            let response = actix_test::call_service(&app, actix_test::TestRequest::get().uri(&uri).to_request()).await;
+            let route = body::to_bytes(response.into_body()).await.unwrap();
+            assert_eq!(route, path.as_str(), "{} is not routed", path);
+        }
+    }
+
+    #[test]
+    fn query_modes_are_documented() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let schemas = &doc["components"]["schemas"];
+
+        let block = &schemas["EthBlocks"]["properties"];
+        assert!(block["base_fee_per_gas"]["description"].as_str().unwrap().contains("?units=dec"));
+        assert_eq!(block["base_fee_per_gas_gwei"]["type"], "string");
+        assert_eq!(block["miner_author_ens"]["type"], "string");
+        assert!(block.get("hash_ens").is_none());
+
+        let transaction = &schemas["EthTransaction"]["properties"];
+        assert_eq!(transaction["value_eth"]["type"], "string");
+        assert_eq!(transaction["from_ens"]["type"], "string");
+
+        assert!(schemas["GasCall"]["properties"].get("to_ens").is_none());
+    }
+}
@@ -0,0 +1,286 @@
+use crate::db::Db;
+use crate::metrics::MeteredHttp;
+use crate::{error::Error::*, Result};
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+use utoipa::ToSchema;
+
+use ethers::providers::{Middleware, Provider};
+use ethers::{
+    abi::{self, ParamType, Token},
+    core::utils::keccak256,
+    types::{U256, H256, Address, Filter, Log, ValueOrArray}
+};
+
+// Event signatures
+const ERC721_TRANSFER: &str = "Transfer(address,address,uint256)";
+const ERC1155_TRANSFER_SINGLE: &str = "TransferSingle(address,address,address,uint256,uint256)";
+const ERC1155_TRANSFER_BATCH: &str = "TransferBatch(address,address,address,uint256[],uint256[])";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+pub enum NftStandard {
+    Erc721,
+    Erc1155,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct NftTransfer {
+    #[schema(value_type = String)]
+    pub contract: Address,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub token_id: U256,
+    pub standard: NftStandard,
+    #[schema(value_type = Option<String>)]
+    pub operator: Option<Address>,
+    #[schema(value_type = String)]
+    pub from: Address,
+    #[schema(value_type = String)]
+    pub to: Address,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub amount: U256,
+    pub block_number: u64,
+    #[schema(value_type = Option<String>)]
+    pub transaction_hash: Option<H256>,
+    pub log_index: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct NftHolder {
+    #[schema(value_type = String)]
+    pub address: Address,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub balance: U256,
+}
+
+/// Current state of a (contract, tokenId) pair, rebuilt from the stored transfers
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct NftToken {
+    #[schema(value_type = String)]
+    pub contract: Address,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub token_id: U256,
+    pub standard: Option<NftStandard>,
+    /// Single owner of an ERC-721 token (or of a fully held ERC-1155 id)
+    #[schema(value_type = Option<String>)]
+    pub owner: Option<Address>,
+    pub holders: Vec<NftHolder>,
+    pub transfers: Vec<NftTransfer>,
+}
+
+fn topic(signature: &str) -> H256 {
+    H256::from(keccak256(signature.as_bytes()))
+}
+
+fn topic_address(topic: &H256) -> Address {
+    Address::from_slice(&topic.as_bytes()[12..])
+}
+
+/// Decode a log into NFT transfers.
+/// An ERC-20 `Transfer` shares the ERC-721 signature but has only 3 topics
+/// (the amount is not indexed), so it is skipped here.
+pub fn decode_transfers(log: &Log) -> Vec<NftTransfer> {
+    let block_number = log.block_number.map(|n| n.as_u64()).unwrap_or_default();
+    let log_index = log.log_index.map(|i| i.as_u64()).unwrap_or_default();
+    let transfer = |standard, operator, from, to, token_id, amount| NftTransfer {
+        contract: log.address,
+        token_id,
+        standard,
+        operator,
+        from,
+        to,
+        amount,
+        block_number,
+        transaction_hash: log.transaction_hash,
+        log_index,
+    };
+
+    let Some(signature) = log.topics.first() else {
+        return vec![];
+    };
+
+    if *signature == topic(ERC721_TRANSFER) && log.topics.len() == 4 {
+        return vec![transfer(
+            NftStandard::Erc721,
+            None,
+            topic_address(&log.topics[1]),
+            topic_address(&log.topics[2]),
+            U256::from_big_endian(log.topics[3].as_bytes()),
+            U256::one(),
+        )];
+    }
+
+    if log.topics.len() != 4 {
+        return vec![];
+    }
+    let operator = Some(topic_address(&log.topics[1]));
+    let from = topic_address(&log.topics[2]);
+    let to = topic_address(&log.topics[3]);
+
+    if *signature == topic(ERC1155_TRANSFER_SINGLE) {
+        let params = [ParamType::Uint(256), ParamType::Uint(256)];
+        if let Ok(tokens) = abi::decode(&params, &log.data) {
+            if let [Token::Uint(id), Token::Uint(value)] = tokens.as_slice() {
+                return vec![transfer(NftStandard::Erc1155, operator, from, to, *id, *value)];
+            }
+        }
+    } else if *signature == topic(ERC1155_TRANSFER_BATCH) {
+        let uint_array = ParamType::Array(Box::new(ParamType::Uint(256)));
+        if let Ok(tokens) = abi::decode(&[uint_array.clone(), uint_array], &log.data) {
+            if let [Token::Array(ids), Token::Array(values)] = tokens.as_slice() {
+                return ids.iter()
+                    .zip(values.iter())
+                    .filter_map(|(id, value)| match (id, value) {
+                        (Token::Uint(id), Token::Uint(value)) => {
+                            Some(transfer(NftStandard::Erc1155, operator, from, to, *id, *value))
+                        },
+                        _ => None,
+                    })
+                    .collect();
+            }
+        }
+    }
+    vec![]
+}
+
+/// Fetch the NFT transfer events of a block and store them
+pub async fn index_block_transfers(
+    provider: Arc<Provider<MeteredHttp>>,
+    db: &Db,
+    block_number: u64
+) -> Result<usize> {
+    let filter = Filter::new()
+        .from_block(block_number)
+        .to_block(block_number)
+        .topic0(ValueOrArray::Array(vec![
+            topic(ERC721_TRANSFER),
+            topic(ERC1155_TRANSFER_SINGLE),
+            topic(ERC1155_TRANSFER_BATCH),
+        ]));
+    let logs = provider.get_logs(&filter).await.map_err(EthProviderErr)?;
+
+    let transfers: Vec<NftTransfer> = logs.iter()
+        .filter(|log| !log.removed.unwrap_or(false))
+        .flat_map(decode_transfers)
+        .collect();
+    db.create_nft_transfers(&transfers).await?;
+    Ok(transfers.len())
+}
+
+/// Replay the transfer history of a token to find its current holders
+pub async fn get_nft_token(db: &Db, contract: Address, token_id: U256) -> Result<NftToken> {
+    let mut transfers = db.fetch_nft_transfers(contract, token_id).await?;
+    transfers.sort_by_key(|transfer| (transfer.block_number, transfer.log_index));
+
+    let mut balances: BTreeMap<Address, U256> = BTreeMap::new();
+    for transfer in transfers.iter() {
+        if !transfer.from.is_zero() {
+            let balance = balances.entry(transfer.from).or_default();
+            *balance = balance.saturating_sub(transfer.amount);
+        }
+        if !transfer.to.is_zero() {
+            let balance = balances.entry(transfer.to).or_default();
+            *balance = balance.saturating_add(transfer.amount);
+        }
+    }
+    let holders: Vec<NftHolder> = balances.into_iter()
+        .filter(|(_, balance)| !balance.is_zero())
+        .map(|(address, balance)| NftHolder { address, balance })
+        .collect();
+    let owner = match holders.as_slice() {
+        [holder] => Some(holder.address),
+        _ => None,
+    };
+
+    Ok(NftToken {
+        contract,
+        token_id,
+        standard: transfers.last().map(|transfer| transfer.standard),
+        owner,
+        holders,
+        transfers,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address_topic(address: Address) -> H256 {
+        H256::from(address)
+    }
+
+    fn log(topics: Vec<H256>, data: Vec<u8>) -> Log {
+        Log {
+            address: Address::repeat_byte(0xaa),
+            topics,
+            data: data.into(),
+            block_number: Some(17.into()),
+            log_index: Some(3.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn decodes_erc721_transfer() {
+        let from = Address::repeat_byte(1);
+        let to = Address::repeat_byte(2);
+        let token_id = H256::from_low_u64_be(42);
+        let transfers = decode_transfers(&log(
+            vec![topic(ERC721_TRANSFER), address_topic(from), address_topic(to), token_id],
+            vec![],
+        ));
+
+        assert_eq!(transfers.len(), 1);
+        let transfer = &transfers[0];
+        assert_eq!(transfer.standard, NftStandard::Erc721);
+        assert_eq!(transfer.from, from);
+        assert_eq!(transfer.to, to);
+        assert_eq!(transfer.token_id, U256::from(42));
+        assert_eq!(transfer.amount, U256::one());
+        assert_eq!(transfer.block_number, 17);
+        assert_eq!(transfer.log_index, 3);
+    }
+
+    #[test]
+    fn skips_erc20_transfer() {
+        let amount = abi::encode(&[Token::Uint(U256::from(1000))]);
+        let transfers = decode_transfers(&log(
+            vec![topic(ERC721_TRANSFER), address_topic(Address::repeat_byte(1)), address_topic(Address::repeat_byte(2))],
+            amount,
+        ));
+        assert!(transfers.is_empty());
+    }
+
+    #[test]
+    fn decodes_erc1155_single_and_batch() {
+        let operator = Address::repeat_byte(9);
+        let topics = |signature| vec![
+            topic(signature),
+            address_topic(operator),
+            address_topic(Address::zero()),
+            address_topic(Address::repeat_byte(2)),
+        ];
+
+        let data = abi::encode(&[Token::Uint(7.into()), Token::Uint(5.into())]);
+        let single = decode_transfers(&log(topics(ERC1155_TRANSFER_SINGLE), data));
+        assert_eq!(single.len(), 1);
+        assert_eq!(single[0].standard, NftStandard::Erc1155);
+        assert_eq!(single[0].operator, Some(operator));
+        assert_eq!(single[0].token_id, U256::from(7));
+        assert_eq!(single[0].amount, U256::from(5));
+
+        let data = abi::encode(&[
+            Token::Array(vec![Token::Uint(1.into()), Token::Uint(2.into())]),
+            Token::Array(vec![Token::Uint(10.into()), Token::Uint(20.into())]),
+        ]);
+        let batch = decode_transfers(&log(topics(ERC1155_TRANSFER_BATCH), data));
+        let pairs: Vec<(U256, U256)> = batch.iter().map(|transfer| (transfer.token_id, transfer.amount)).collect();
+        assert_eq!(pairs, vec![(1.into(), 10.into()), (2.into(), 20.into())]);
+    }
+}
@@ -0,0 +1,240 @@
+use crate::config;
+use crate::eth_explore::{self, AggregatorInterface};
+use crate::metrics::MeteredHttp;
+use crate::{error::Error::*, Result};
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, OnceLock};
+use serde::{Serialize, Deserialize};
+use utoipa::ToSchema;
+
+use ethers::providers::Provider;
+use ethers::{
+    core::utils::format_units,
+    types::{U256, Address}
+};
+
+// Conversion rates are fixed point numbers with 18 decimals
+const RATE_DECIMALS: u32 = 18;
+// An answer older than the feed heartbeat plus this grace period is stale
+const HEARTBEAT_GRACE_PERIOD: u64 = 3600;
+
+/// Chainlink feed: price of 1 `base` in `quote`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PriceFeed {
+    pub base: String,
+    pub quote: String,
+    pub address: Address,
+    /// Seconds between two updates of the answer
+    pub heartbeat: u64,
+}
+
+impl PriceFeed {
+    /// Seconds after which the answer is considered stale
+    pub fn max_age(&self) -> u64 {
+        self.heartbeat + HEARTBEAT_GRACE_PERIOD
+    }
+}
+
+/// Mainnet Chainlink feeds (base, quote, address, heartbeat)
+const DEFAULT_PRICE_FEEDS: [(&str, &str, &str, u64); 11] = [
+    ("ETH", "USD", "0x5f4eC3Df9cbd43714FE2740f5E3616155c5b8419", 3600),
+    ("BTC", "USD", "0xF4030086522a5bEEa4988F8cA5B36dbC97BeE88c", 3600),
+    ("BTC", "ETH", "0xdeb288F737066589598e9214E782fa5A8eD689e8", 86400),
+    ("EUR", "USD", "0xb49f677943BC038e9857d61E7d053CaA2C1734C1", 86400),
+    ("GBP", "USD", "0x5c0Ab2d9b5a7ed9f470386e82BB36A3613cDd4b5", 86400),
+    ("USDC", "USD", "0x8fFfFfd4AfB6115b954Bd326cbe7B4BA576818f6", 86400),
+    ("USDC", "ETH", "0x986b5E1e1755e3C2440e960477f25201B0a8bbD4", 86400),
+    ("USDT", "USD", "0x3E7d1eAB13ad0104d2750B8863b489D65364e32D", 86400),
+    ("DAI", "USD", "0xAed0c38402a5d19df6E4c03F4E2DceD6e29c1ee9", 3600),
+    ("LINK", "USD", "0x2c1d072e956AFFC0D435Cb7AC38EF18d24d9127c", 3600),
+    ("LINK", "ETH", "0xDC530D9457755926550b59e8ECcdaE7624181557", 21600),
+];
+
+static PRICE_FEEDS: OnceLock<Vec<PriceFeed>> = OnceLock::new();
+
+fn default_price_feeds() -> Vec<PriceFeed> {
+    DEFAULT_PRICE_FEEDS.iter()
+        .map(|(base, quote, address, heartbeat)| PriceFeed {
+            base: base.to_string(),
+            quote: quote.to_string(),
+            address: address.parse().expect("Valid address"),
+            heartbeat: *heartbeat,
+        })
+        .collect()
+}
+
+/// Feed registry: the JSON array of feeds in the file `PRICE_FEEDS_FILE`,
+/// the mainnet feeds when it is not set
+pub fn price_feeds() -> Result<&'static [PriceFeed]> {
+    if let Some(feeds) = PRICE_FEEDS.get() {
+        return Ok(feeds);
+    }
+    let feeds = config::json_file::<Vec<PriceFeed>>("PRICE_FEEDS_FILE")?
+        .unwrap_or_else(default_price_feeds)
+        .into_iter()
+        .map(|feed| PriceFeed {
+            base: feed.base.to_uppercase(),
+            quote: feed.quote.to_uppercase(),
+            ..feed
+        })
+        .collect();
+    Ok(PRICE_FEEDS.get_or_init(|| feeds))
+}
+
+/// Registry feed giving the price of `base` in `quote`
+pub fn find_feed(base: &str, quote: &str) -> Result<&'static PriceFeed> {
+    price_feeds()?
+        .iter()
+        .find(|feed| feed.base == base && feed.quote == quote)
+        .ok_or_else(|| ConfigError(format!("no {}/{} feed in the price feed registry", base, quote)))
+}
+
+/// Price of `base` in `quote`, possibly chained through several feeds
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct Price {
+    pub base: String,
+    pub quote: String,
+    /// 18 decimals
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub rate: U256,
+    pub price: f64,
+    /// Feeds used, e.g. `["ETH/USD", "EUR/USD"]`
+    pub path: Vec<String>,
+}
+
+/// Parse `ETH-EUR`, `eth_eur` or `ETH/EUR`
+pub fn parse_pair(pair: &str) -> Result<(String, String)> {
+    let pair = pair.to_uppercase();
+    match pair.split(['-', '_', '/']).collect::<Vec<&str>>().as_slice() {
+        [base, quote] if !base.is_empty() && !quote.is_empty() => Ok((base.to_string(), quote.to_string())),
+        _ => Err(InvalidParam(format!("pair {}", pair))),
+    }
+}
+
+// Shortest chain of feeds from `base` to `quote`, `true` when a feed is used inverted
+fn find_path<'a>(feeds: &'a [PriceFeed], base: &'a str, quote: &str) -> Option<Vec<(&'a PriceFeed, bool)>> {
+    let mut previous: HashMap<&str, (&str, &PriceFeed, bool)> = HashMap::new();
+    let mut queue: VecDeque<&str> = VecDeque::from([base]);
+    while let Some(asset) = queue.pop_front() {
+        if asset == quote {
+            let mut path = vec![];
+            let mut current = quote;
+            while current != base {
+                let (from, feed, inverted) = previous[current];
+                path.push((feed, inverted));
+                current = from;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for feed in feeds.iter() {
+            let next = if feed.base == asset {
+                Some((feed.quote.as_str(), false))
+            } else if feed.quote == asset {
+                Some((feed.base.as_str(), true))
+            } else {
+                None
+            };
+            if let Some((next, inverted)) = next {
+                if next != base && !previous.contains_key(next) {
+                    previous.insert(next, (asset, feed, inverted));
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Checked answer of a feed scaled to 18 decimals
+pub async fn get_feed_rate(provider: Arc<Provider<MeteredHttp>>, feed: &PriceFeed) -> Result<U256> {
+    let oracle = AggregatorInterface::new(feed.address, provider);
+    let round_data = oracle.latest_round_data().call().await.map_err(EthOracleErr)?;
+    let decimals = oracle.decimals().call().await.map_err(EthOracleErr)?;
+    let answer = eth_explore::check_round_data(round_data, feed.max_age(), config::unix_now())?;
+    Ok(eth_explore::scale_decimals(answer, u32::from(decimals), RATE_DECIMALS))
+}
+
+/// Price of `base` in `quote` by chaining the registry feeds (token -> ETH -> USD -> EUR)
+pub async fn get_price(provider: Arc<Provider<MeteredHttp>>, base: &str, quote: &str) -> Result<Price> {
+    let one = U256::exp10(RATE_DECIMALS as usize);
+    let path = find_path(price_feeds()?, base, quote)
+        .ok_or_else(|| NotFound(format!("no price feed path from {} to {}", base, quote)))?;
+
+    let mut rate = one;
+    for (feed, inverted) in path.iter() {
+        let feed_rate = get_feed_rate(provider.clone(), feed).await?;
+        rate = if *inverted {
+            rate * one / feed_rate
+        } else {
+            rate * feed_rate / one
+        };
+    }
+
+    Ok(Price {
+        base: base.to_string(),
+        quote: quote.to_string(),
+        rate,
+        price: format_units(rate, RATE_DECIMALS)
+            .map_err(EthConvErr)?
+            .parse::<f64>()
+            .map_err(EthConvStrErr)?,
+        path: path.iter().map(|(feed, _)| format!("{}/{}", feed.base, feed.quote)).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(feeds: &[PriceFeed], base: &str, quote: &str) -> Option<Vec<(String, bool)>> {
+        find_path(feeds, base, quote).map(|path| path.iter()
+            .map(|(feed, inverted)| (format!("{}/{}", feed.base, feed.quote), *inverted))
+            .collect())
+    }
+
+    #[test]
+    fn pairs_accept_several_separators() {
+        assert_eq!(parse_pair("eth-eur").unwrap(), ("ETH".to_string(), "EUR".to_string()));
+        assert_eq!(parse_pair("LINK_usd").unwrap(), ("LINK".to_string(), "USD".to_string()));
+        assert_eq!(parse_pair("BTC/ETH").unwrap(), ("BTC".to_string(), "ETH".to_string()));
+        assert!(parse_pair("ETH").is_err());
+        assert!(parse_pair("ETH-").is_err());
+        assert!(parse_pair("ETH-USD-EUR").is_err());
+    }
+
+    #[test]
+    fn feeds_are_chained() {
+        let feeds = default_price_feeds();
+        assert_eq!(path(&feeds, "ETH", "USD"), Some(vec![("ETH/USD".to_string(), false)]));
+        assert_eq!(path(&feeds, "USD", "ETH"), Some(vec![("ETH/USD".to_string(), true)]));
+        assert_eq!(
+            path(&feeds, "ETH", "EUR"),
+            Some(vec![("ETH/USD".to_string(), false), ("EUR/USD".to_string(), true)])
+        );
+        assert_eq!(path(&feeds, "ETH", "JPY"), None);
+    }
+
+    #[test]
+    fn answers_go_stale_after_the_heartbeat_and_grace_period() {
+        let feeds = default_price_feeds();
+        let eth_usd = feeds.iter().find(|feed| feed.base == "ETH" && feed.quote == "USD").unwrap();
+        assert_eq!(eth_usd.max_age(), 2 * 3600);
+        let eur_usd = feeds.iter().find(|feed| feed.base == "EUR" && feed.quote == "USD").unwrap();
+        assert_eq!(eur_usd.max_age(), 86400 + 3600);
+    }
+
+    #[test]
+    fn registry_is_read_from_json() {
+        let feeds: Vec<PriceFeed> = serde_json::from_str(r#"[
+            { "base": "ETH", "quote": "USD", "address": "0x5f4eC3Df9cbd43714FE2740f5E3616155c5b8419", "heartbeat": 3600 },
+            { "base": "JPY", "quote": "USD", "address": "0xBcE206caE7f0ec07b545EddE332A47C2F75bbeb3", "heartbeat": 86400 }
+        ]"#).unwrap();
+        assert_eq!(
+            path(&feeds, "ETH", "JPY"),
+            Some(vec![("ETH/USD".to_string(), false), ("JPY/USD".to_string(), true)])
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use crate::db::Db;
+use crate::ens;
+use crate::metrics::MeteredHttp;
+use crate::{error::Error::*, Result};
+
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+use utoipa::ToSchema;
+
+use ethers::providers::{Middleware, Provider};
+use ethers::types::{U64, H256, Address};
+
+/// What the query was recognised as
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchKind {
+    Block,
+    Transaction,
+    Address,
+    Ens,
+}
+
+/// Where the result was found
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchSource {
+    Db,
+    Rpc,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct SearchResult {
+    pub query: String,
+    pub kind: SearchKind,
+    pub source: SearchSource,
+    /// API path of the result, e.g. `/block/17000000`
+    pub path: String,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = Option<String>, format = "quantity")]
+    pub block_number: Option<U64>,
+    #[schema(value_type = Option<String>)]
+    pub hash: Option<H256>,
+    #[schema(value_type = Option<String>)]
+    pub address: Option<Address>,
+    pub name: Option<String>,
+}
+
+impl SearchResult {
+    fn new(query: &str, kind: SearchKind, source: SearchSource, path: String) -> SearchResult {
+        SearchResult {
+            query: query.to_string(),
+            kind,
+            source,
+            path,
+            block_number: None,
+            hash: None,
+            address: None,
+            name: None,
+        }
+    }
+
+    fn block(query: &str, source: SearchSource, number: Option<U64>, hash: Option<H256>) -> SearchResult {
+        let path = format!("/block/{}", number.unwrap_or_default());
+        SearchResult { block_number: number, hash, ..SearchResult::new(query, SearchKind::Block, source, path) }
+    }
+
+    fn transaction(query: &str, source: SearchSource, number: Option<U64>, hash: H256) -> SearchResult {
+        let path = format!("/tx/{:?}", hash);
+        SearchResult { block_number: number, hash: Some(hash), ..SearchResult::new(query, SearchKind::Transaction, source, path) }
+    }
+
+    fn address(query: &str, kind: SearchKind, source: SearchSource, address: Address) -> SearchResult {
+        let path = format!("/address/{:?}", address);
+        SearchResult { address: Some(address), ..SearchResult::new(query, kind, source, path) }
+    }
+}
+
+async fn search_block_number(provider: Arc<Provider<MeteredHttp>>, db: &Db, query: &str, number: u64) -> Result<SearchResult> {
+    if let Some(block) = db.fetch_ethblocks(U64::from(number)).await? {
+        return Ok(SearchResult::block(query, SearchSource::Db, block.number, block.hash));
+    }
+    let block = provider.get_block(number).await.map_err(EthProviderErr)?
+        .ok_or_else(|| NotFound(format!("block {}", number)))?;
+    Ok(SearchResult::block(query, SearchSource::Rpc, block.number, block.hash))
+}
+
+// 32 bytes: a transaction or a block hash
+async fn search_hash(provider: Arc<Provider<MeteredHttp>>, db: &Db, query: &str, hash: H256) -> Result<SearchResult> {
+    if let Some(block) = db.fetch_ethblocks_by_transaction(hash).await? {
+        return Ok(SearchResult::transaction(query, SearchSource::Db, block.number, hash));
+    }
+    if let Some(block) = db.fetch_ethblocks_by_hash(hash).await? {
+        return Ok(SearchResult::block(query, SearchSource::Db, block.number, block.hash));
+    }
+    if let Some(transaction) = provider.get_transaction(hash).await.map_err(EthProviderErr)? {
+        return Ok(SearchResult::transaction(query, SearchSource::Rpc, transaction.block_number, hash));
+    }
+    if let Some(block) = provider.get_block(hash).await.map_err(EthProviderErr)? {
+        return Ok(SearchResult::block(query, SearchSource::Rpc, block.number, block.hash));
+    }
+    Err(NotFound(format!("no block or transaction {:?}", hash)))
+}
+
+async fn search_address(db: &Db, query: &str, address: Address) -> Result<SearchResult> {
+    // Any address is valid, `db` tells it has stored transactions
+    let activity = db.fetch_address_activity(address).await?;
+    let source = if activity.transaction_count > 0 { SearchSource::Db } else { SearchSource::Rpc };
+    Ok(SearchResult::address(query, SearchKind::Address, source, address))
+}
+
+async fn search_ens(provider: Arc<Provider<MeteredHttp>>, db: &Db, query: &str) -> Result<SearchResult> {
+    let record = ens::resolve_name(provider, db, query).await?;
+    let address = record.address.ok_or_else(|| NotFound(format!("ENS name {}", query)))?;
+    let source = if record.cached { SearchSource::Db } else { SearchSource::Rpc };
+    let mut result = SearchResult::address(query, SearchKind::Ens, source, address);
+    result.name = record.name;
+    Ok(result)
+}
+
+// What a search query was parsed as
+#[derive(Debug, PartialEq, Eq)]
+enum Query<'a> {
+    BlockNumber(u64),
+    Hash(H256),
+    Address(Address),
+    Ens(&'a str),
+}
+
+fn classify(query: &str) -> Result<Query<'_>> {
+    if query.is_empty() {
+        return Err(InvalidParam("empty search".to_string()));
+    }
+    if let Ok(number) = query.parse::<u64>() {
+        return Ok(Query::BlockNumber(number));
+    }
+    if let Some(hex) = query.strip_prefix("0x").or_else(|| query.strip_prefix("0X")) {
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(InvalidParam(format!("search {}", query)));
+        }
+        return match hex.len() {
+            64 => Ok(Query::Hash(hex.parse().map_err(|_| InvalidParam(format!("hash {}", query)))?)),
+            40 => Ok(Query::Address(hex.parse().map_err(|_| InvalidParam(format!("address {}", query)))?)),
+            1..=16 => {
+                let number = u64::from_str_radix(hex, 16).map_err(|_| InvalidParam(format!("block {}", query)))?;
+                Ok(Query::BlockNumber(number))
+            },
+            _ => Err(InvalidParam(format!("search {}", query))),
+        };
+    }
+    if query.contains('.') {
+        return Ok(Query::Ens(query));
+    }
+    Err(InvalidParam(format!("search {}", query)))
+}
+
+/// Classify `query` (block number, block / transaction hash, address or ENS name)
+/// and look it up in the database, then with the RPC
+pub async fn search(provider: Arc<Provider<MeteredHttp>>, db: &Db, query: &str) -> Result<SearchResult> {
+    let query = query.trim();
+    match classify(query)? {
+        Query::BlockNumber(number) => search_block_number(provider, db, query, number).await,
+        Query::Hash(hash) => search_hash(provider, db, query, hash).await,
+        Query::Address(address) => search_address(db, query, address).await,
+        Query::Ens(name) => search_ens(provider, db, name).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_are_classified() {
+        assert_eq!(classify("17000000").unwrap(), Query::BlockNumber(17_000_000));
+        assert_eq!(classify("0x1036640").unwrap(), Query::BlockNumber(17_000_000));
+        let hash = H256::repeat_byte(0xab);
+        assert_eq!(classify(&format!("{:?}", hash)).unwrap(), Query::Hash(hash));
+        let address = Address::repeat_byte(0xcd);
+        assert_eq!(classify(&format!("{:?}", address)).unwrap(), Query::Address(address));
+        assert_eq!(classify(&format!("0X{}", "CD".repeat(20))).unwrap(), Query::Address(address));
+        assert_eq!(classify("vitalik.eth").unwrap(), Query::Ens("vitalik.eth"));
+    }
+
+    #[test]
+    fn invalid_queries() {
+        for query in ["", "vitalik", "0x", "0xzz", "0x12345678901234567", &"f".repeat(40)] {
+            assert!(matches!(classify(query), Err(InvalidParam(_))), "{}", query);
+        }
+    }
+}
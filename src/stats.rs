@@ -0,0 +1,588 @@
+use crate::db::Db;
+use crate::eth_explore::EthBlocks;
+use crate::uncles;
+use crate::valuation;
+use crate::Result;
+
+use std::collections::{BTreeMap, HashMap};
+use serde::{Serialize, Deserialize};
+use utoipa::ToSchema;
+
+use ethers::types::{U64, U256, I256, H256};
+
+/// Aggregation bucket of the `/stats` endpoints
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Bucket {
+    #[default]
+    Block,
+    Minute,
+    Hour,
+    Day,
+}
+
+impl Bucket {
+    /// Block number for `Block`, start of the period (unix time) otherwise
+    pub fn key(&self, stats: &BlockStats) -> u64 {
+        let timestamp = stats.timestamp;
+        match self {
+            Bucket::Block => stats.number,
+            Bucket::Minute => timestamp - timestamp % 60,
+            Bucket::Hour => timestamp - timestamp % 3600,
+            Bucket::Day => timestamp - timestamp % 86400,
+        }
+    }
+}
+
+/// EIP-1559 fees of a block
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BlockBurn {
+    /// `base_fee_per_gas * gas_used`
+    pub burnt_fees: U256,
+    /// `blob_base_fee * blob_gas_used` (EIP-4844, burnt as well)
+    pub blob_burnt_fees: U256,
+    /// Tips paid to the proposer, needs the receipts
+    pub priority_fees: U256,
+}
+
+pub fn block_burn(block: &EthBlocks) -> BlockBurn {
+    let base_fee = block.base_fee_per_gas.unwrap_or_default();
+    let burnt_fees = base_fee * block.gas_used.unwrap_or_default();
+    let blob_burnt_fees = block.blob_base_fee.unwrap_or_default() * block.blob_gas_used.unwrap_or_default();
+    let priority_fees = block.transactions.iter()
+        .filter_map(|tx| Some((tx.effective_gas_price?, tx.gas_used?)))
+        .fold(U256::zero(), |sum, (effective_gas_price, gas_used)| {
+            sum + effective_gas_price.saturating_sub(base_fee) * gas_used
+        });
+    BlockBurn {
+        burnt_fees,
+        blob_burnt_fees,
+        priority_fees,
+    }
+}
+
+/// Number of transactions per EIP-2718 type
+#[derive(Serialize, Deserialize, Debug, Clone, Default, ToSchema)]
+pub struct TransactionTypeCounts {
+    pub legacy: u64,
+    pub access_list: u64,
+    pub eip1559: u64,
+    pub blob: u64,
+}
+
+/// Per-block reductions stored in the `block_stats` collection,
+/// kept in sync with `eth_blocks` by `Db`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, ToSchema)]
+pub struct BlockStats {
+    pub number: u64,
+    #[schema(value_type = Option<String>)]
+    pub hash: Option<H256>,
+    pub timestamp: u64,
+    pub transaction_count: u64,
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub total_value: U256,
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub total_fees: U256,
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub burnt_fees: U256,
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub blob_burnt_fees: U256,
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub priority_fees: U256,
+    /// Effective gas price when the receipt is known, gas price otherwise
+    #[serde(serialize_with = "crate::units::wei_per_gas")]
+    #[schema(value_type = String, format = "wei-per-gas")]
+    pub min_gas_price: U256,
+    #[serde(serialize_with = "crate::units::wei_per_gas")]
+    #[schema(value_type = String, format = "wei-per-gas")]
+    pub avg_gas_price: U256,
+    #[serde(serialize_with = "crate::units::wei_per_gas")]
+    #[schema(value_type = String, format = "wei-per-gas")]
+    pub median_gas_price: U256,
+    #[serde(serialize_with = "crate::units::wei_per_gas")]
+    #[schema(value_type = String, format = "wei-per-gas")]
+    pub max_gas_price: U256,
+    #[serde(serialize_with = "crate::units::wei_per_gas")]
+    #[schema(value_type = String, format = "wei-per-gas")]
+    pub gas_price_sum: U256,
+    pub priced_transactions: u64,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub gas_used: U256,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub gas_limit: U256,
+    /// `gas_used / gas_limit`
+    pub gas_utilization: f64,
+    pub transaction_types: TransactionTypeCounts,
+    pub contract_creations: u64,
+    pub uncle_count: u64,
+    #[serde(default)]
+    pub withdrawal_count: u64,
+    /// Beacon chain withdrawals (wei)
+    #[serde(default, serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub withdrawn: U256,
+    #[serde(default, serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub blob_gas_used: U256,
+    #[serde(default, serialize_with = "crate::units::wei_per_gas")]
+    #[schema(value_type = String, format = "wei-per-gas")]
+    pub blob_base_fee: U256,
+}
+
+// Withdrawal amounts are in gwei
+const WEI_PER_GWEI: u64 = 1_000_000_000;
+
+// `num / den` as a float with 6 decimals
+fn ratio(num: U256, den: U256) -> f64 {
+    if den.is_zero() {
+        return 0.0;
+    }
+    (num * U256::from(1_000_000) / den).low_u64() as f64 / 1_000_000.0
+}
+
+impl BlockStats {
+    pub fn from_block(block: &EthBlocks) -> BlockStats {
+        let burn = block_burn(block);
+        let mut stats = BlockStats {
+            number: block.number.unwrap_or_default().as_u64(),
+            hash: block.hash,
+            timestamp: block.timestamp.low_u64(),
+            transaction_count: block.transaction_number,
+            burnt_fees: burn.burnt_fees,
+            blob_burnt_fees: burn.blob_burnt_fees,
+            priority_fees: burn.priority_fees,
+            gas_used: block.gas_used.unwrap_or_default(),
+            gas_limit: block.gas_limit.unwrap_or_default(),
+            uncle_count: block.uncles.len() as u64,
+            withdrawal_count: block.withdrawals.len() as u64,
+            withdrawn: block.withdrawals.iter()
+                .fold(U256::zero(), |sum, withdrawal| sum + withdrawal.amount * U256::from(WEI_PER_GWEI)),
+            blob_gas_used: block.blob_gas_used.unwrap_or_default(),
+            blob_base_fee: block.blob_base_fee.unwrap_or_default(),
+            ..Default::default()
+        };
+
+        let mut gas_prices: Vec<U256> = vec![];
+        for transaction in block.transactions.iter() {
+            stats.total_value += transaction.value;
+            stats.total_fees += valuation::transaction_fee(transaction).unwrap_or_default();
+            if let Some(gas_price) = transaction.effective_gas_price.or(transaction.gas_price) {
+                gas_prices.push(gas_price);
+            }
+            match transaction.transaction_type.map(|t| t.as_u64()).unwrap_or_default() {
+                1 => stats.transaction_types.access_list += 1,
+                2 => stats.transaction_types.eip1559 += 1,
+                3 => stats.transaction_types.blob += 1,
+                _ => stats.transaction_types.legacy += 1,
+            }
+            if transaction.to.is_none() {
+                stats.contract_creations += 1;
+            }
+        }
+
+        if !gas_prices.is_empty() {
+            gas_prices.sort();
+            stats.gas_price_sum = gas_prices.iter().fold(U256::zero(), |sum, gas_price| sum + gas_price);
+            stats.priced_transactions = gas_prices.len() as u64;
+            stats.min_gas_price = gas_prices[0];
+            stats.avg_gas_price = stats.gas_price_sum / U256::from(gas_prices.len());
+            stats.median_gas_price = gas_prices[gas_prices.len() / 2];
+            stats.max_gas_price = gas_prices[gas_prices.len() - 1];
+        }
+        stats.gas_utilization = ratio(stats.gas_used, stats.gas_limit);
+        stats
+    }
+}
+
+/// Rebuild the `block_stats` collection from the stored blocks
+pub async fn rebuild_block_stats(db: &Db) -> Result<usize> {
+    let blocks = db.fetch_all_ethblocks().await?;
+    for block in blocks.iter() {
+        db.save_block_stats(&BlockStats::from_block(block)).await?;
+    }
+    Ok(blocks.len())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, ToSchema)]
+pub struct BurnBucket {
+    pub bucket: u64,
+    pub blocks: u64,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub gas_used: U256,
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub burnt_fees: U256,
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub blob_burnt_fees: U256,
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub priority_fees: U256,
+    /// Execution layer issuance (block and uncle rewards, zero after the merge)
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub issuance: U256,
+    /// `issuance - burnt fees` in wei, as a signed decimal string
+    pub net_issuance: String,
+}
+
+impl BurnBucket {
+    fn add(&mut self, stats: &BlockStats, issuance: U256) {
+        self.blocks += 1;
+        self.gas_used += stats.gas_used;
+        self.burnt_fees += stats.burnt_fees;
+        self.blob_burnt_fees += stats.blob_burnt_fees;
+        self.priority_fees += stats.priority_fees;
+        self.issuance += issuance;
+    }
+
+    fn close(mut self) -> Self {
+        let burnt = I256::from_raw(self.burnt_fees + self.blob_burnt_fees);
+        self.net_issuance = (I256::from_raw(self.issuance) - burnt).to_string();
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct BurnStats {
+    pub from: u64,
+    pub to: u64,
+    pub bucket: Bucket,
+    pub total: BurnBucket,
+    pub series: Vec<BurnBucket>,
+}
+
+/// Burn, priority fees and issuance over the stored blocks `from..=to`
+pub async fn get_burn_stats(db: &Db, from: u64, to: u64, bucket: Bucket) -> Result<BurnStats> {
+    let block_stats = db.fetch_block_stats_range(from, to).await?;
+
+    // Uncle rewards are paid to the uncle miners by the nephew block
+    let mut uncle_rewards: BTreeMap<u64, U256> = BTreeMap::new();
+    for uncle in db.fetch_uncles_range(from, to).await? {
+        *uncle_rewards.entry(uncle.nephew_number).or_default() += uncle.reward;
+    }
+
+    let mut total = BurnBucket { bucket: from, ..Default::default() };
+    let mut series: BTreeMap<u64, BurnBucket> = BTreeMap::new();
+    for stats in block_stats.iter() {
+        let reward = uncles::block_reward(stats.number);
+        let issuance = reward
+            + reward / U256::from(32) * U256::from(stats.uncle_count)
+            + uncle_rewards.get(&stats.number).copied().unwrap_or_default();
+
+        let key = bucket.key(stats);
+        series.entry(key)
+            .or_insert_with(|| BurnBucket { bucket: key, ..Default::default() })
+            .add(stats, issuance);
+        total.add(stats, issuance);
+    }
+
+    Ok(BurnStats {
+        from,
+        to,
+        bucket,
+        total: total.close(),
+        series: series.into_values().map(BurnBucket::close).collect(),
+    })
+}
+
+/// Transaction and gas statistics of a bucket
+#[derive(Serialize, Deserialize, Debug, Clone, Default, ToSchema)]
+pub struct SeriesBucket {
+    pub bucket: u64,
+    pub blocks: u64,
+    pub transaction_count: u64,
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub total_value: U256,
+    /// Effective gas price when the receipt is known, gas price otherwise
+    #[serde(serialize_with = "crate::units::wei_per_gas")]
+    #[schema(value_type = String, format = "wei-per-gas")]
+    pub avg_gas_price: U256,
+    #[serde(serialize_with = "crate::units::wei_per_gas")]
+    #[schema(value_type = String, format = "wei-per-gas")]
+    pub median_gas_price: U256,
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub total_fees: U256,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub gas_used: U256,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub gas_limit: U256,
+    /// `gas_used / gas_limit`
+    pub gas_utilization: f64,
+    pub withdrawal_count: u64,
+    /// Beacon chain withdrawals (wei)
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub withdrawn: U256,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub blob_gas_used: U256,
+    #[serde(serialize_with = "crate::units::wei_per_gas")]
+    #[schema(value_type = String, format = "wei-per-gas")]
+    pub avg_blob_base_fee: U256,
+    #[serde(skip)]
+    #[schema(value_type = String)]
+    gas_price_sum: U256,
+    #[serde(skip)]
+    #[schema(value_type = String)]
+    blob_base_fee_sum: U256,
+    #[serde(skip)]
+    priced_transactions: u64,
+    // Prices of the transactions of a wider bucket, for its median
+    #[serde(skip)]
+    #[schema(value_type = Vec<String>)]
+    gas_prices: Vec<U256>,
+}
+
+impl SeriesBucket {
+    fn add(&mut self, stats: &BlockStats) {
+        self.blocks += 1;
+        self.transaction_count += stats.transaction_count;
+        self.total_value += stats.total_value;
+        self.total_fees += stats.total_fees;
+        self.gas_used += stats.gas_used;
+        self.gas_limit += stats.gas_limit;
+        self.gas_price_sum += stats.gas_price_sum;
+        self.priced_transactions += stats.priced_transactions;
+        self.withdrawal_count += stats.withdrawal_count;
+        self.withdrawn += stats.withdrawn;
+        self.blob_gas_used += stats.blob_gas_used;
+        self.blob_base_fee_sum += stats.blob_base_fee;
+    }
+
+    fn close(mut self) -> Self {
+        if self.priced_transactions > 0 {
+            self.avg_gas_price = self.gas_price_sum / U256::from(self.priced_transactions);
+        }
+        if !self.gas_prices.is_empty() {
+            self.gas_prices.sort();
+            self.median_gas_price = self.gas_prices[self.gas_prices.len() / 2];
+        }
+        if self.blocks > 0 {
+            self.avg_blob_base_fee = self.blob_base_fee_sum / U256::from(self.blocks);
+        }
+        self.gas_utilization = ratio(self.gas_used, self.gas_limit);
+        self
+    }
+}
+
+/// First and last stored blocks, `None` when no block is stored
+#[derive(Serialize, Deserialize, Debug, Clone, Default, ToSchema)]
+pub struct StoredRange {
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+}
+
+/// Gas prices of a stored block, read from `eth_blocks` for the bucket medians
+#[derive(Deserialize, Debug, Clone)]
+pub struct BlockGasPrices {
+    pub number: U64,
+    #[serde(default)]
+    pub transactions: Vec<TransactionGasPrice>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TransactionGasPrice {
+    pub gas_price: Option<U256>,
+    pub effective_gas_price: Option<U256>,
+}
+
+/// Per block / minute / hour / day statistics over the stored blocks `from..=to`
+pub async fn get_series_stats(db: &Db, from: u64, to: u64, bucket: Bucket) -> Result<Vec<SeriesBucket>> {
+    let block_stats = db.fetch_block_stats_range(from, to).await?;
+    // The median of a wider bucket is taken over the prices of all its transactions
+    let gas_prices = match bucket {
+        Bucket::Block => vec![],
+        _ => db.fetch_block_gas_prices_range(from, to).await?,
+    };
+    Ok(series_stats(&block_stats, &gas_prices, bucket))
+}
+
+fn series_stats(block_stats: &[BlockStats], gas_prices: &[BlockGasPrices], bucket: Bucket) -> Vec<SeriesBucket> {
+    let gas_prices: HashMap<u64, &[TransactionGasPrice]> = gas_prices.iter()
+        .map(|block| (block.number.as_u64(), block.transactions.as_slice()))
+        .collect();
+    let mut series: BTreeMap<u64, SeriesBucket> = BTreeMap::new();
+    for stats in block_stats.iter() {
+        let key = bucket.key(stats);
+        let series_bucket = series.entry(key)
+            .or_insert_with(|| SeriesBucket { bucket: key, ..Default::default() });
+        series_bucket.add(stats);
+        if bucket == Bucket::Block {
+            series_bucket.median_gas_price = stats.median_gas_price;
+        } else if let Some(transactions) = gas_prices.get(&stats.number) {
+            series_bucket.gas_prices.extend(transactions.iter()
+                .filter_map(|transaction| transaction.effective_gas_price.or(transaction.gas_price)));
+        }
+    }
+    series.into_values().map(SeriesBucket::close).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::Address;
+    use serde_json::json;
+
+    fn stats(number: u64, timestamp: u64) -> BlockStats {
+        BlockStats {
+            number,
+            timestamp,
+            transaction_count: 2,
+            total_value: U256::from(100),
+            gas_used: U256::from(15),
+            gas_limit: U256::from(30),
+            gas_price_sum: U256::from(60),
+            priced_transactions: 2,
+            withdrawal_count: 16,
+            withdrawn: U256::from(1000),
+            blob_gas_used: U256::from(131_072),
+            blob_base_fee: U256::from(number),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn bucket_keys() {
+        let stats = stats(18_000_000, 1_700_000_123);
+        assert_eq!(Bucket::Block.key(&stats), 18_000_000);
+        assert_eq!(Bucket::Minute.key(&stats), 1_700_000_100);
+        assert_eq!(Bucket::Hour.key(&stats), 1_699_999_200);
+        assert_eq!(Bucket::Day.key(&stats), 1_699_920_000);
+    }
+
+    #[test]
+    fn blocks_are_reduced_per_bucket() {
+        // 5 blocks 12s apart: 1_700_000_040 starts a new minute
+        let block_stats: Vec<BlockStats> = (0..5)
+            .map(|i| stats(100 + i, 1_699_999_992 + 12 * i))
+            .collect();
+
+        let per_block = series_stats(&block_stats, &[], Bucket::Block);
+        assert_eq!(per_block.len(), 5);
+        assert_eq!(per_block[0].avg_gas_price, U256::from(30));
+        assert_eq!(per_block[0].gas_utilization, 0.5);
+
+        let per_minute = series_stats(&block_stats, &[], Bucket::Minute);
+        let keys: Vec<(u64, u64)> = per_minute.iter().map(|bucket| (bucket.bucket, bucket.blocks)).collect();
+        assert_eq!(keys, vec![(1_699_999_980, 4), (1_700_000_040, 1)]);
+
+        let minute = &per_minute[0];
+        assert_eq!(minute.transaction_count, 8);
+        assert_eq!(minute.total_value, U256::from(400));
+        assert_eq!(minute.withdrawal_count, 64);
+        assert_eq!(minute.withdrawn, U256::from(4000));
+        assert_eq!(minute.blob_gas_used, U256::from(4 * 131_072));
+        // (100 + 101 + 102 + 103) / 4
+        assert_eq!(minute.avg_blob_base_fee, U256::from(101));
+        assert_eq!(minute.avg_gas_price, U256::from(30));
+    }
+
+    #[test]
+    fn block_gas_price_reductions() {
+        let transaction = |hash: u64, gas_price: u64| json!({
+            "hash": format!("{:?}", H256::from_low_u64_be(hash)),
+            "from": format!("{:?}", Address::zero()),
+            "value": "0x0",
+            "gas": "0x5208",
+            "gas_price": format!("{:#x}", gas_price),
+        });
+        let block: EthBlocks = serde_json::from_value(json!({
+            "number": "0x64",
+            "timestamp": "0x0",
+            "transaction_number": 3,
+            "transactions": [transaction(1, 30), transaction(2, 10), transaction(3, 20)],
+        })).unwrap();
+
+        let stats = BlockStats::from_block(&block);
+        assert_eq!(stats.min_gas_price, U256::from(10));
+        assert_eq!(stats.median_gas_price, U256::from(20));
+        assert_eq!(stats.max_gas_price, U256::from(30));
+    }
+
+    #[test]
+    fn bucket_median_spans_all_transactions() {
+        let with_median = |number: u64, median_gas_price: u64| BlockStats {
+            median_gas_price: U256::from(median_gas_price),
+            ..stats(number, 1_700_000_000)
+        };
+        // Read from `eth_blocks`, the effective gas price wins over the gas price
+        let gas_prices: Vec<BlockGasPrices> = serde_json::from_value(json!([
+            { "number": "0x64", "transactions": [
+                { "gas_price": "0x1" },
+                { "gas_price": "0x9", "effective_gas_price": "0x2" },
+                { "gas_price": "0x3" },
+            ] },
+            { "number": "0x65", "transactions": [{ "gas_price": "0xa" }, { "gas_price": "0xb" }] },
+        ])).unwrap();
+        let block_stats = vec![with_median(100, 2), with_median(101, 11)];
+
+        // Block medians 2 and 11, transaction median 3
+        let per_minute = series_stats(&block_stats, &gas_prices, Bucket::Minute);
+        assert_eq!(per_minute[0].median_gas_price, U256::from(3));
+
+        // One block per bucket: its stored median
+        let per_block = series_stats(&block_stats, &[], Bucket::Block);
+        assert_eq!(per_block[1].median_gas_price, U256::from(11));
+    }
+
+    #[test]
+    fn block_fees_are_split_into_burn_and_tips() {
+        let block: EthBlocks = serde_json::from_value(json!({
+            "number": "0x64",
+            "timestamp": "0x0",
+            "gas_used": "0x5208",
+            "base_fee_per_gas": "0xa",
+            "blob_gas_used": "0x20000",
+            "blob_base_fee": "0x2",
+            "transaction_number": 2,
+            "transactions": [
+                // 21000 gas at 13 wei, 3 wei above the base fee
+                {
+                    "hash": format!("{:?}", H256::from_low_u64_be(1)),
+                    "from": format!("{:?}", Address::zero()),
+                    "value": "0x0",
+                    "gas": "0x5208",
+                    "gas_used": "0x5208",
+                    "effective_gas_price": "0xd",
+                },
+                // No receipt, no tip
+                {
+                    "hash": format!("{:?}", H256::from_low_u64_be(2)),
+                    "from": format!("{:?}", Address::zero()),
+                    "value": "0x0",
+                    "gas": "0x5208",
+                },
+            ],
+        })).unwrap();
+
+        let burn = block_burn(&block);
+        assert_eq!(burn.burnt_fees, U256::from(210_000));
+        assert_eq!(burn.blob_burnt_fees, U256::from(262_144));
+        assert_eq!(burn.priority_fees, U256::from(63_000));
+    }
+
+    #[test]
+    fn net_issuance_is_signed() {
+        let stats = BlockStats { burnt_fees: U256::from(300), blob_burnt_fees: U256::from(50), ..Default::default() };
+        let mut bucket = BurnBucket::default();
+        bucket.add(&stats, U256::from(100));
+        assert_eq!(bucket.close().net_issuance, "-250");
+
+        let mut bucket = BurnBucket::default();
+        bucket.add(&stats, U256::from(1000));
+        assert_eq!(bucket.close().net_issuance, "650");
+    }
+}
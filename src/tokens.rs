@@ -0,0 +1,119 @@
+use crate::db::Db;
+use crate::config;
+use crate::metrics::MeteredHttp;
+use crate::{error::Error::*, Result};
+
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+use utoipa::ToSchema;
+
+use ethers::providers::{Middleware, Provider};
+use ethers::{
+    contract::abigen,
+    types::{U256, Address}
+};
+
+// Cached metadata is resolved again after one hour (totalSupply moves)
+const TOKEN_CACHE_TTL: u64 = 3600;
+
+abigen!(
+    Erc20Interface,
+    r#"[
+        function name() external view returns (string)
+        function symbol() external view returns (string)
+        function decimals() external view returns (uint8)
+        function totalSupply() external view returns (uint256)
+    ]"#,
+);
+
+// Some early tokens (MKR, SAI...) return name and symbol as bytes32
+mod bytes32 {
+    use ethers::contract::abigen;
+
+    abigen!(
+        Erc20Bytes32Interface,
+        r#"[
+            function name() external view returns (bytes32)
+            function symbol() external view returns (bytes32)
+        ]"#,
+    );
+}
+use bytes32::Erc20Bytes32Interface;
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct TokenMetadata {
+    #[schema(value_type = String)]
+    pub address: Address,
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: Option<u8>,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = Option<String>, format = "quantity")]
+    pub total_supply: Option<U256>,
+    /// Unix time of the last on-chain resolution
+    pub resolved_at: u64,
+}
+
+fn bytes32_to_string(bytes: [u8; 32]) -> Option<String> {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8(bytes[..end].to_vec()).ok()
+}
+
+/// Resolve name, symbol, decimals and totalSupply with `eth_call`.
+/// Missing or reverting methods are left to `None`.
+pub async fn resolve_token(provider: Arc<Provider<MeteredHttp>>, address: Address) -> Result<TokenMetadata> {
+    let code = provider.get_code(address, None).await.map_err(EthProviderErr)?;
+    if code.as_ref().is_empty() {
+        return Err(NotFound(format!("no contract at {:?}", address)));
+    }
+
+    let token = Erc20Interface::new(address, Arc::clone(&provider));
+    let token_bytes32 = Erc20Bytes32Interface::new(address, Arc::clone(&provider));
+
+    let name = match token.name().call().await {
+        Ok(name) => Some(name),
+        Err(_) => token_bytes32.name().call().await.ok().and_then(bytes32_to_string),
+    };
+    let symbol = match token.symbol().call().await {
+        Ok(symbol) => Some(symbol),
+        Err(_) => token_bytes32.symbol().call().await.ok().and_then(bytes32_to_string),
+    };
+    let decimals = token.decimals().call().await.ok();
+    let total_supply = token.total_supply().call().await.ok();
+
+    Ok(TokenMetadata {
+        address,
+        name,
+        symbol,
+        decimals,
+        total_supply,
+        resolved_at: config::unix_now(),
+    })
+}
+
+/// Get the token metadata from the `tokens` collection,
+/// resolving it on-chain when unknown or outdated
+pub async fn get_token(provider: Arc<Provider<MeteredHttp>>, db: &Db, address: Address) -> Result<TokenMetadata> {
+    if let Some(token) = db.fetch_token(address).await? {
+        if config::unix_now().saturating_sub(token.resolved_at) < TOKEN_CACHE_TTL {
+            return Ok(token);
+        }
+    }
+    let token = resolve_token(provider, address).await?;
+    db.save_token(&token).await?;
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes32_names_are_trimmed_at_the_first_zero() {
+        let mut bytes = [0u8; 32];
+        bytes[..5].copy_from_slice(b"Maker");
+        assert_eq!(bytes32_to_string(bytes), Some("Maker".to_string()));
+        assert_eq!(bytes32_to_string([0u8; 32]), Some(String::new()));
+        assert_eq!(bytes32_to_string([0xff; 32]), None);
+    }
+}
@@ -0,0 +1,163 @@
+use crate::db::Db;
+use crate::metrics::MeteredHttp;
+use crate::{error::Error::*, Result};
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+use utoipa::ToSchema;
+
+use ethers::providers::{Middleware, Provider};
+use ethers::types::{U64, U256, H256, Address};
+
+const WEI_PER_ETH: u64 = 1_000_000_000_000_000_000;
+const BYZANTIUM_BLOCK: u64 = 4_370_000;
+const CONSTANTINOPLE_BLOCK: u64 = 7_280_000;
+const MERGE_BLOCK: u64 = 15_537_394;
+
+/// Uncle (ommer) header with the block that included it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EthUncle {
+    pub number: u64,
+    pub hash: Option<H256>,
+    pub miner_author: Option<Address>,
+    pub timestamp: U256,
+    pub nephew_number: u64,
+    pub nephew_hash: Option<H256>,
+    pub uncle_index: u64,
+    /// Reward paid to the uncle miner (wei)
+    pub reward: U256,
+}
+
+/// Rewards earned by a miner over a block range (transaction fees excluded)
+#[derive(Serialize, Deserialize, Debug, Clone, Default, ToSchema)]
+pub struct MinerRewards {
+    #[schema(value_type = String)]
+    pub miner: Address,
+    pub blocks: u64,
+    pub uncles: u64,
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub block_rewards: U256,
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub inclusion_rewards: U256,
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub uncle_rewards: U256,
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub total_rewards: U256,
+}
+
+/// Static block reward of the era, no issuance for miners after the merge
+pub fn block_reward(block_number: u64) -> U256 {
+    let eth = match block_number {
+        n if n >= MERGE_BLOCK => 0,
+        n if n >= CONSTANTINOPLE_BLOCK => 2,
+        n if n >= BYZANTIUM_BLOCK => 3,
+        _ => 5,
+    };
+    U256::from(eth) * U256::from(WEI_PER_ETH)
+}
+
+/// Uncle miner reward: (uncle + 8 - nephew) / 8 of the block reward
+pub fn uncle_reward(uncle_number: u64, nephew_number: u64) -> U256 {
+    let distance = (uncle_number + 8).saturating_sub(nephew_number);
+    block_reward(nephew_number) * U256::from(distance) / U256::from(8)
+}
+
+/// Fetch the uncles of a block with `eth_getUncleByBlockNumberAndIndex` and store them
+pub async fn index_block_uncles(
+    provider: Arc<Provider<MeteredHttp>>,
+    db: &Db,
+    nephew_number: u64,
+    nephew_hash: Option<H256>,
+    uncle_count: usize
+) -> Result<usize> {
+    let mut uncles: Vec<EthUncle> = vec![];
+    for uncle_index in 0..uncle_count {
+        let uncle = provider
+            .get_uncle(nephew_number, U64::from(uncle_index))
+            .await
+            .map_err(EthProviderErr)?;
+        if let Some(uncle) = uncle {
+            let number = uncle.number.unwrap_or_default().as_u64();
+            uncles.push(EthUncle {
+                number,
+                hash: uncle.hash,
+                miner_author: uncle.author,
+                timestamp: uncle.timestamp,
+                nephew_number,
+                nephew_hash,
+                uncle_index: uncle_index as u64,
+                reward: uncle_reward(number, nephew_number),
+            });
+        }
+    }
+    db.create_uncles(&uncles).await?;
+    Ok(uncles.len())
+}
+
+/// Block, uncle inclusion and uncle rewards per miner from the stored blocks and uncles
+pub async fn get_miner_rewards(db: &Db, from: u64, to: u64) -> Result<Vec<MinerRewards>> {
+    let blocks = db.fetch_ethblocks_range(from, to, false).await?;
+    let uncles = db.fetch_uncles_range(from, to).await?;
+
+    let mut rewards: BTreeMap<Address, MinerRewards> = BTreeMap::new();
+    for block in blocks.iter() {
+        let (Some(number), Some(miner)) = (block.number, block.miner_author) else {
+            continue;
+        };
+        let reward = block_reward(number.as_u64());
+        let miner_rewards = rewards.entry(miner).or_insert_with(|| MinerRewards { miner, ..Default::default() });
+        miner_rewards.blocks += 1;
+        miner_rewards.block_rewards += reward;
+        miner_rewards.inclusion_rewards += reward / U256::from(32) * U256::from(block.uncles.len());
+    }
+    for uncle in uncles.iter() {
+        let Some(miner) = uncle.miner_author else {
+            continue;
+        };
+        let miner_rewards = rewards.entry(miner).or_insert_with(|| MinerRewards { miner, ..Default::default() });
+        miner_rewards.uncles += 1;
+        miner_rewards.uncle_rewards += uncle.reward;
+    }
+
+    let mut rewards: Vec<MinerRewards> = rewards.into_values()
+        .map(|mut miner_rewards| {
+            miner_rewards.total_rewards = miner_rewards.block_rewards
+                + miner_rewards.inclusion_rewards
+                + miner_rewards.uncle_rewards;
+            miner_rewards
+        })
+        .collect();
+    rewards.sort_by(|a, b| b.total_rewards.cmp(&a.total_rewards).then(b.blocks.cmp(&a.blocks)));
+    Ok(rewards)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eth(amount: u64) -> U256 {
+        U256::from(amount) * U256::from(WEI_PER_ETH)
+    }
+
+    #[test]
+    fn block_reward_by_era() {
+        assert_eq!(block_reward(BYZANTIUM_BLOCK - 1), eth(5));
+        assert_eq!(block_reward(BYZANTIUM_BLOCK), eth(3));
+        assert_eq!(block_reward(CONSTANTINOPLE_BLOCK), eth(2));
+        assert_eq!(block_reward(MERGE_BLOCK - 1), eth(2));
+        assert_eq!(block_reward(MERGE_BLOCK), U256::zero());
+    }
+
+    #[test]
+    fn uncle_reward_decreases_with_distance() {
+        let nephew = CONSTANTINOPLE_BLOCK + 100;
+        assert_eq!(uncle_reward(nephew - 1, nephew), eth(2) * 7 / 8);
+        assert_eq!(uncle_reward(nephew - 6, nephew), eth(2) * 2 / 8);
+        assert_eq!(uncle_reward(nephew - 8, nephew), U256::zero());
+    }
+}
@@ -0,0 +1,308 @@
+use std::cell::Cell;
+use serde::{Serialize, Serializer, Deserialize};
+use serde::ser::SerializeMap;
+use serde_json::Value;
+use utoipa::{IntoParams, ToSchema};
+use utoipa::openapi::{RefOr, Schema, SchemaFormat, schema::{Object, ObjectBuilder, SchemaType}};
+
+use actix_web::HttpResponse;
+use ethers::{
+    core::utils::format_units,
+    types::{U256, U64}
+};
+
+/// Representation of the quantities in the API responses (`?units=`)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    /// Hex strings, as serialized by ethers (default)
+    #[default]
+    Hex,
+    /// Decimal strings
+    Dec,
+    /// Decimal strings with a `<field>_eth` or `<field>_gwei` companion
+    Eth,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UnitsQuery {
+    #[serde(default)]
+    pub units: Units,
+}
+
+thread_local! {
+    // `?units=` of the response serialized by `to_json`, quantities stay
+    // in ethers hex everywhere else (database documents, plain JSON)
+    static UNITS: Cell<Units> = const { Cell::new(Units::Hex) };
+}
+
+/// Value of a quantity field: `U256`, `U64` or an `Option` of them
+pub trait Quantity: Serialize {
+    fn quantity(&self) -> Option<U256>;
+}
+
+impl Quantity for U256 {
+    fn quantity(&self) -> Option<U256> {
+        Some(*self)
+    }
+}
+
+impl Quantity for U64 {
+    fn quantity(&self) -> Option<U256> {
+        Some(U256::from(self.as_u64()))
+    }
+}
+
+impl<T: Quantity> Quantity for Option<T> {
+    fn quantity(&self) -> Option<U256> {
+        self.as_ref().and_then(Quantity::quantity)
+    }
+}
+
+// `?units=eth` companion of a quantity: unit given to `format_units`, key suffix
+type Companion = (&'static str, &'static str);
+
+const ETHER: Companion = ("ether", "eth");
+const GWEI: Companion = ("gwei", "gwei");
+
+// Keys of the placeholder written for a quantity with a companion, `to_json`
+// replaces it with the decimal string and adds the `<field>_<suffix>` sibling
+const DECIMAL_KEY: &str = "$decimal";
+const COMPANION_KEY: &str = "$companion";
+
+fn format_trimmed(quantity: U256, units: &str) -> Option<String> {
+    let formatted = format_units(quantity, units).ok()?;
+    let formatted = formatted.trim_end_matches('0').trim_end_matches('.');
+    Some(formatted.to_string())
+}
+
+fn serialize_quantity<T: Quantity, S: Serializer>(value: &T, companion: Option<Companion>, serializer: S) -> Result<S::Ok, S::Error> {
+    let Some(quantity) = value.quantity() else {
+        return value.serialize(serializer);
+    };
+    match (UNITS.with(Cell::get), companion) {
+        (Units::Hex, _) => value.serialize(serializer),
+        (Units::Eth, Some((unit, suffix))) => match format_trimmed(quantity, unit) {
+            Some(formatted) => {
+                let mut placeholder = serializer.serialize_map(Some(2))?;
+                placeholder.serialize_entry(DECIMAL_KEY, &quantity.to_string())?;
+                placeholder.serialize_entry(COMPANION_KEY, &(suffix, formatted))?;
+                placeholder.end()
+            },
+            None => serializer.serialize_str(&quantity.to_string()),
+        },
+        _ => serializer.serialize_str(&quantity.to_string()),
+    }
+}
+
+/// `serialize_with` of the amounts in wei: hex, decimal, with a `<field>_eth` companion
+pub fn wei<T: Quantity, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serialize_quantity(value, Some(ETHER), serializer)
+}
+
+/// `serialize_with` of the prices in wei per gas: hex, decimal, with a `<field>_gwei` companion
+pub fn wei_per_gas<T: Quantity, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serialize_quantity(value, Some(GWEI), serializer)
+}
+
+/// `serialize_with` of the other quantities (gas, numbers, token amounts): hex or decimal
+pub fn quantity<T: Quantity, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serialize_quantity(value, None, serializer)
+}
+
+// Replace the placeholders of the quantities by their decimal string and companion
+fn add_companions(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            let mut companions: Vec<(String, Value)> = vec![];
+            for (key, value) in object.iter_mut() {
+                match take_placeholder(value) {
+                    Some((decimal, suffix, formatted)) => {
+                        *value = decimal;
+                        companions.push((format!("{}_{}", key, suffix), formatted));
+                    },
+                    None => add_companions(value),
+                }
+            }
+            object.extend(companions);
+        },
+        Value::Array(values) => values.iter_mut().for_each(add_companions),
+        _ => {},
+    }
+}
+
+fn take_placeholder(value: &mut Value) -> Option<(Value, String, Value)> {
+    let placeholder = value.as_object_mut()?;
+    if placeholder.len() != 2 {
+        return None;
+    }
+    let decimal = placeholder.get(DECIMAL_KEY)?.clone();
+    let Value::Array(companion) = placeholder.get(COMPANION_KEY)? else {
+        return None;
+    };
+    match companion.as_slice() {
+        [Value::String(suffix), formatted] => Some((decimal, suffix.clone(), formatted.clone())),
+        _ => None,
+    }
+}
+
+const UNITS_NOTE: &str = "Hex quantity, decimal string with `?units=dec` or `?units=eth`";
+
+fn append_description(description: &mut Option<String>, note: &str) {
+    *description = Some(match description.take() {
+        Some(description) => format!("{}. {}", description.trim_end_matches('.'), note),
+        None => note.to_string(),
+    });
+}
+
+/// Document the `?units=` representations in the properties of an OpenAPI object:
+/// the quantity fields (`format` `wei`, `wei-per-gas` or `quantity`) are hex or
+/// decimal strings, `?units=eth` adds the `_eth` and `_gwei` companions
+pub fn document_schema(object: &mut Object) {
+    let mut companions: Vec<(String, RefOr<Schema>)> = vec![];
+    for (key, property) in object.properties.iter_mut() {
+        let quantity = match property {
+            RefOr::T(Schema::Object(quantity)) if is_quantity(quantity) => quantity,
+            _ => continue,
+        };
+        append_description(&mut quantity.description, UNITS_NOTE);
+        let unit = match &quantity.format {
+            Some(SchemaFormat::Custom(format)) if format == "wei" => ETHER,
+            Some(SchemaFormat::Custom(format)) if format == "wei-per-gas" => GWEI,
+            _ => continue,
+        };
+        let companion = ObjectBuilder::new()
+            .schema_type(SchemaType::String)
+            .nullable(quantity.nullable)
+            .description(Some(format!("`{}` in {}, with `?units=eth`", key, unit.0)))
+            .build();
+        companions.push((format!("{}_{}", key, unit.1), companion.into()));
+    }
+    object.properties.extend(companions);
+}
+
+fn is_quantity(schema: &Object) -> bool {
+    matches!(
+        &schema.format,
+        Some(SchemaFormat::Custom(format)) if ["wei", "wei-per-gas", "quantity"].contains(&format.as_str())
+    )
+}
+
+/// Serialize `body` with its quantities in `units`
+pub fn to_json<T: Serialize>(body: &T, units: Units) -> Value {
+    let previous = UNITS.with(|current| current.replace(units));
+    let value = serde_json::to_value(body);
+    UNITS.with(|current| current.set(previous));
+    let mut value = value.unwrap_or(Value::Null);
+    if units == Units::Eth {
+        add_companions(&mut value);
+    }
+    value
+}
+
+/// `200 OK` JSON response with the quantities in `units`
+pub fn json<T: Serialize>(body: &T, units: Units) -> HttpResponse {
+    HttpResponse::Ok().json(to_json(body, units))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Amounts {
+        #[serde(serialize_with = "wei")]
+        value: U256,
+        #[serde(serialize_with = "wei_per_gas")]
+        base_fee: Option<U256>,
+        #[serde(serialize_with = "wei_per_gas")]
+        blob_base_fee: Option<U256>,
+        #[serde(serialize_with = "quantity")]
+        number: U64,
+        // Not a quantity field, whatever its name
+        fee: U256,
+        nested: Vec<Nested>,
+    }
+
+    #[derive(Serialize)]
+    struct Nested {
+        #[serde(serialize_with = "wei")]
+        amount: U256,
+    }
+
+    fn amounts() -> Amounts {
+        Amounts {
+            value: U256::exp10(18),
+            base_fee: Some(U256::exp10(9)),
+            blob_base_fee: None,
+            number: U64::from(42),
+            fee: U256::from(16),
+            nested: vec![Nested { amount: U256::exp10(17) }],
+        }
+    }
+
+    #[test]
+    fn hex_is_the_ethers_serialization() {
+        let value = to_json(&amounts(), Units::Hex);
+        assert_eq!(value, serde_json::to_value(amounts()).unwrap());
+        assert_eq!(value["value"], "0xde0b6b3a7640000");
+        assert_eq!(value["number"], "0x2a");
+    }
+
+    #[test]
+    fn dec_converts_the_quantity_fields() {
+        let value = to_json(&amounts(), Units::Dec);
+        assert_eq!(value["value"], "1000000000000000000");
+        assert_eq!(value["base_fee"], "1000000000");
+        assert_eq!(value["blob_base_fee"], Value::Null);
+        assert_eq!(value["number"], "42");
+        assert_eq!(value["fee"], "0x10");
+        assert_eq!(value["nested"][0]["amount"], "100000000000000000");
+        assert!(value.get("value_eth").is_none());
+    }
+
+    #[test]
+    fn eth_adds_companions_to_the_amounts() {
+        let value = to_json(&amounts(), Units::Eth);
+        assert_eq!(value["value"], "1000000000000000000");
+        assert_eq!(value["value_eth"], "1");
+        assert_eq!(value["base_fee"], "1000000000");
+        assert_eq!(value["base_fee_gwei"], "1");
+        assert_eq!(value["nested"][0]["amount_eth"], "0.1");
+        // No companion for the other quantities and the missing amounts
+        assert!(value.get("number_eth").is_none());
+        assert!(value.get("blob_base_fee_gwei").is_none());
+        assert!(value.get("fee_eth").is_none());
+    }
+
+    #[test]
+    fn units_only_apply_inside_to_json() {
+        to_json(&amounts(), Units::Eth);
+        assert_eq!(serde_json::to_value(amounts()).unwrap()["value"], "0xde0b6b3a7640000");
+    }
+
+    #[test]
+    fn schema_follows_the_quantity_formats() {
+        let property = |format: &str| ObjectBuilder::new()
+            .schema_type(SchemaType::String)
+            .format(Some(SchemaFormat::Custom(format.to_string())))
+            .build();
+        let mut object = ObjectBuilder::new()
+            .property("value", property("wei"))
+            .property("base_fee", property("wei-per-gas"))
+            .property("number", property("quantity"))
+            .property("hash", ObjectBuilder::new().schema_type(SchemaType::String))
+            .build();
+        document_schema(&mut object);
+
+        let keys: Vec<&str> = object.properties.keys().map(String::as_str).collect();
+        assert_eq!(keys, ["base_fee", "base_fee_gwei", "hash", "number", "value", "value_eth"]);
+        let description = |key: &str| match &object.properties[key] {
+            RefOr::T(Schema::Object(property)) => property.description.clone(),
+            _ => None,
+        };
+        assert!(description("number").unwrap().contains("?units=dec"));
+        assert!(description("hash").is_none());
+    }
+}
@@ -0,0 +1,209 @@
+use crate::db::Db;
+use crate::eth_explore::{self, EthBlocks, EthTransaction};
+use crate::metrics::MeteredHttp;
+use crate::{error::Error::*, Result};
+
+use std::sync::Arc;
+use std::time::Instant;
+use serde::{Serialize, Deserialize};
+use utoipa::ToSchema;
+
+use ethers::providers::{Middleware, Provider};
+use ethers::types::{U64, U256, H256};
+
+/// Transaction value and fee at the ETH/USD price of its block
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct TransactionUsd {
+    #[schema(value_type = String)]
+    pub hash: H256,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = Option<String>, format = "quantity")]
+    pub block_number: Option<U64>,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub eth_usd_price: U256,
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub value: U256,
+    pub value_usd: f64,
+    /// `gas_used * effective_gas_price`, needs the receipt
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = Option<String>, format = "wei")]
+    pub fee: Option<U256>,
+    pub fee_usd: Option<f64>,
+}
+
+/// Block totals at the ETH/USD price of the block
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct BlockUsd {
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = Option<String>, format = "quantity")]
+    pub number: Option<U64>,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub timestamp: U256,
+    #[serde(serialize_with = "crate::units::quantity")]
+    #[schema(value_type = String, format = "quantity")]
+    pub eth_usd_price: U256,
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub value: U256,
+    pub value_usd: f64,
+    #[serde(serialize_with = "crate::units::wei")]
+    #[schema(value_type = String, format = "wei")]
+    pub fees: U256,
+    pub fees_usd: f64,
+    pub transactions: Vec<TransactionUsd>,
+}
+
+/// Fee paid by a transaction
+pub fn transaction_fee(transaction: &EthTransaction) -> Option<U256> {
+    Some(transaction.gas_used? * transaction.effective_gas_price?)
+}
+
+fn block_price(block: &EthBlocks) -> Result<U256> {
+    block.eth_usd_price.ok_or_else(|| {
+        NotFound(format!("no ETH/USD price for block {}", block.number.unwrap_or_default()))
+    })
+}
+
+pub fn transaction_usd(block: &EthBlocks, transaction: &EthTransaction) -> Result<TransactionUsd> {
+    let eth_usd_price = block_price(block)?;
+    let fee = transaction_fee(transaction);
+    Ok(TransactionUsd {
+        hash: transaction.hash,
+        block_number: block.number,
+        eth_usd_price,
+        value: transaction.value,
+        value_usd: eth_explore::usd_value(transaction.value, eth_usd_price)?,
+        fee,
+        fee_usd: fee.map(|fee| eth_explore::usd_value(fee, eth_usd_price)).transpose()?,
+    })
+}
+
+pub fn block_usd(block: &EthBlocks) -> Result<BlockUsd> {
+    let eth_usd_price = block_price(block)?;
+    let transactions = block.transactions.iter()
+        .map(|transaction| transaction_usd(block, transaction))
+        .collect::<Result<Vec<TransactionUsd>>>()?;
+    let value = block.transactions.iter().fold(U256::zero(), |sum, tx| sum + tx.value);
+    let fees = transactions.iter().fold(U256::zero(), |sum, tx| sum + tx.fee.unwrap_or_default());
+    Ok(BlockUsd {
+        number: block.number,
+        timestamp: block.timestamp,
+        eth_usd_price,
+        value,
+        value_usd: eth_explore::usd_value(value, eth_usd_price)?,
+        fees,
+        fees_usd: eth_explore::usd_value(fees, eth_usd_price)?,
+        transactions,
+    })
+}
+
+/// Record the ETH/USD price of the downloaded blocks that do not have one yet
+pub async fn backfill_block_prices(provider: Arc<Provider<MeteredHttp>>, db: Db, from: u64) -> Result<()> {
+    let to = provider.get_block_number().await.map_err(EthProviderErr)?.as_u64();
+
+    // Start time
+    let start = Instant::now();
+    let mut price_number: usize = 0;
+
+    for block_number in from..=to {
+        if let Some(mut block) = db.fetch_ethblocks(U64::from(block_number)).await? {
+            if block.eth_usd_price.is_some() {
+                continue;
+            }
+            match eth_explore::get_eth_usd_price_at(provider.clone(), block_number, block.timestamp).await {
+                Ok(price) => {
+                    block.eth_usd_price = Some(price);
+                    db.edit_ethblocks(&block).await?;
+                    price_number += 1;
+                },
+                Err(err) => eprintln!("Error getting ETH/USD price of block {}: {}", block_number, err),
+            }
+
+            print!("{}", (8u8 as char));
+            print!(
+                "Pricing... Block {} ({:.2} Blocks/s):\r",
+                block_number,
+                ((block_number - from) as f64) / start.elapsed().as_secs_f64()
+            );
+        }
+    }
+    println!("{} block prices recorded", price_number);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::Address;
+
+    const ETHER: u64 = 1_000_000_000_000_000_000;
+
+    fn transaction(value: u64, gas_used: Option<u64>) -> EthTransaction {
+        EthTransaction {
+            hash: H256::from_low_u64_be(value),
+            from: Address::zero(),
+            to: None,
+            value: U256::from(value),
+            gas_price: None,
+            gas: U256::from(21_000),
+            transaction_type: None,
+            max_fee_per_blob_gas: None,
+            blob_versioned_hashes: vec![],
+            gas_used: gas_used.map(U256::from),
+            effective_gas_price: gas_used.map(|_| U256::from(10_000_000_000u64)),
+            status: None,
+            contract_address: None,
+            method_id: None,
+        }
+    }
+
+    fn block(eth_usd_price: Option<u64>, transactions: Vec<EthTransaction>) -> EthBlocks {
+        EthBlocks {
+            number: Some(U64::from(100)),
+            hash: None,
+            parent_hash: None,
+            miner_author: None,
+            timestamp: U256::zero(),
+            gas_used: None,
+            gas_limit: None,
+            base_fee_per_gas: None,
+            transaction_number: transactions.len() as u64,
+            transactions,
+            withdrawals: vec![],
+            uncles: vec![],
+            blob_gas_used: None,
+            excess_blob_gas: None,
+            blob_base_fee: None,
+            // 8 decimals
+            eth_usd_price: eth_usd_price.map(|price| U256::from(price) * U256::exp10(8)),
+        }
+    }
+
+    #[test]
+    fn transactions_are_valued_at_the_block_price() {
+        let block = block(Some(2000), vec![transaction(ETHER / 2, Some(21_000))]);
+        let usd = transaction_usd(&block, &block.transactions[0]).unwrap();
+        assert_eq!(usd.value_usd, 1000.0);
+        // 21000 gas at 10 gwei
+        assert_eq!(usd.fee, Some(U256::from(210_000_000_000_000u64)));
+        assert_eq!(usd.fee_usd, Some(0.42));
+    }
+
+    #[test]
+    fn block_totals() {
+        let block = block(Some(2000), vec![transaction(ETHER, Some(21_000)), transaction(ETHER, None)]);
+        let usd = block_usd(&block).unwrap();
+        assert_eq!(usd.value, U256::from(2 * ETHER));
+        assert_eq!(usd.value_usd, 4000.0);
+        assert_eq!(usd.fees_usd, 0.42);
+        assert_eq!(usd.transactions[1].fee_usd, None);
+    }
+
+    #[test]
+    fn blocks_without_price_are_not_found() {
+        assert!(matches!(block_usd(&block(None, vec![])), Err(NotFound(_))));
+    }
+}